@@ -4,6 +4,7 @@ import socket
 import signal
 import sys
 import os
+import select
 
 
 HOST = 'localhost'
@@ -35,6 +36,34 @@ def	send_data(str):
 	except Exception as e:
 		print("Error when sending data:", e)
 
+# Forward the terminal to the pseudo-terminal of a program until the server
+# closes the connection (Ctrl-] detaches without stopping the program)
+def attach(arg):
+	try:
+		sock = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+		sock.connect((HOST, PORT))
+		sock.send(f"attach {arg}".encode('utf-8'))
+	except Exception as e:
+		print("Error when sending data:", e)
+		return
+	fd = sys.stdin.fileno()
+	old_tty_setting = termios.tcgetattr(fd)
+	try:
+		tty.setraw(fd, termios.TCSADRAIN)
+		while (True):
+			readable, _, _ = select.select([sock, fd], [], [])
+			if sock in readable:
+				receive = sock.recv(1024)
+				if not receive:
+					break
+				os.write(sys.stdout.fileno(), receive)
+			if fd in readable:
+				sock.send(os.read(fd, 1024))
+	finally:
+		termios.tcsetattr(fd, termios.TCSADRAIN, old_tty_setting)
+		sock.close()
+		print("")
+
 class InputInterpretor(cmd.Cmd):
 	prompt = 'Taskmaster > '
 
@@ -47,29 +76,56 @@ class InputInterpretor(cmd.Cmd):
 		print("Type 'help' for all commands.")
 	
 	def do_status(self, arg):
-		"""Print the status of all the programs"""
+		"""Print the status of all the programs, or of [program[:number]] or [group:*]"""
 		send_data(f"status {arg}")
 
 	def do_start(self, arg):
-		"""Start the program specified in argument"""
+		"""Start the program specified in argument: [program[:number]], [group:*] or [group:program]"""
 		send_data(f"start {arg}")
 	
 	def do_stop(self, arg):
-		"""Stop the program specified in argument"""
+		"""Stop the programs specified in argument: all, web*, api worker:2..."""
 		send_data(f"stop {arg}")
 
 	def do_restart(self, arg=None):
-		"""Restart the program specified in argument"""
+		"""Restart the program specified in argument, --rolling [--batch=N] restarts N processes at a time"""
 		send_data(f"restart {arg}")
 	
+	def do_scale(self, arg):
+		"""Change the number of processes of a program: scale [program] [numprocs]"""
+		send_data(f"scale {arg}")
+
+	def do_write(self, arg):
+		"""Write a line to the stdin of a program: write [program[:number]] [data], \\c at the end omits the line feed"""
+		send_data(f"write {arg}")
+
+	def do_closestdin(self, arg):
+		"""Close the stdin of a program, it receives EOF"""
+		send_data(f"closestdin {arg}")
+
+	def do_attach(self, arg):
+		"""Attach to the terminal of a program started with pty=true, Ctrl-] to detach"""
+		attach(arg)
+
 	def do_quit(self, arg):
 		"""Disconnect the client and quit program"""
 		sys.exit(0)
 
 	def do_reload(self, arg):
-		"""Reload the config file"""
-		os.system("pkill -hup taskmaster")
-		print("taskmaster config file is reloaded")
+		"""Reload the config file, the previous config is kept if it has errors"""
+		send_data("reload")
+
+	def do_avail(self, arg):
+		"""List the jobs added, changed or removed in the config file"""
+		send_data("avail")
+
+	def do_update(self, arg):
+		"""Apply the config file to the given jobs only: update [job...], all of them without argument"""
+		send_data(f"update {arg}")
+
+	def do_config(self, arg):
+		"""config diff: show the differences between the applied config and the config file"""
+		send_data(f"config {arg}")
 
 	def do_kill(self, arg):
 		"""Kill the server"""
@@ -0,0 +1,130 @@
+// Socket activation: a listening socket bound once by taskmaster and shared
+// by the processes of a job, with the conventions of systemd
+// https://www.freedesktop.org/software/systemd/man/sd_listen_fds.html
+
+use crate::error::{Error, Result};
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+// first passed fd, after stdin, stdout and stderr
+const SD_LISTEN_FDS_START: i32 = 3;
+const F_SETFD: i32 = 2;
+const POLLIN: i16 = 1;
+// LISTEN_PID must be the pid of the process, which is only known once it is
+// forked: a shell sets it, then execs the command in its place
+pub const LISTEN_PID_WRAPPER: &str = "export LISTEN_PID=$$; exec \"$0\" \"$@\"";
+
+extern "C" {
+    fn dup2(old_fd: i32, new_fd: i32) -> i32;
+    fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+    fn poll(fds: *mut PollFd, nfds: u64, timeout: i32) -> i32;
+}
+
+#[repr(C)]
+struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SocketAddress {
+    // host:port
+    Tcp(String),
+    // absolute path
+    Unix(String),
+}
+
+// `tcp://host:port` or `unix:///path`
+pub fn parse_socket_address(raw: &str) -> Option<SocketAddress> {
+    if let Some(address) = raw.strip_prefix("tcp://") {
+        let (host, port) = address.rsplit_once(':')?;
+        if host.is_empty() || port.parse::<u16>().is_err() {
+            return None;
+        }
+        return Some(SocketAddress::Tcp(address.to_string()));
+    }
+    let path: &str = raw.strip_prefix("unix://")?;
+    match path.starts_with('/') {
+        true => Some(SocketAddress::Unix(path.to_string())),
+        false => None,
+    }
+}
+
+#[derive(Debug)]
+pub enum ListenSocket {
+    Tcp(TcpListener),
+    Unix(UnixListener, String),
+}
+
+impl ListenSocket {
+    pub fn bind(address: &SocketAddress) -> Result<ListenSocket> {
+        let to_error = |e: io::Error| Error::StartJobFail(format!("{address:?}: {e}"));
+        match address {
+            SocketAddress::Tcp(address) => Ok(ListenSocket::Tcp(
+                TcpListener::bind(address).map_err(to_error)?,
+            )),
+            SocketAddress::Unix(path) => {
+                // left by a previous instance
+                if fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+                    let _ = fs::remove_file(path);
+                }
+                Ok(ListenSocket::Unix(
+                    UnixListener::bind(path).map_err(to_error)?,
+                    path.clone(),
+                ))
+            }
+        }
+    }
+
+    fn raw_fd(&self) -> i32 {
+        match self {
+            ListenSocket::Tcp(listener) => listener.as_raw_fd(),
+            ListenSocket::Unix(listener, _) => listener.as_raw_fd(),
+        }
+    }
+
+    // A connection waits to be accepted
+    pub fn has_pending_connection(&self) -> bool {
+        let mut poll_fd: PollFd = PollFd {
+            fd: self.raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        };
+        unsafe { poll(&mut poll_fd, 1, 0) == 1 && poll_fd.revents & POLLIN != 0 }
+    }
+
+    // Pass the socket as fd 3 with LISTEN_FDS=1, the command must be wrapped
+    // by LISTEN_PID_WRAPPER
+    pub fn apply(&self, command: &mut Command) {
+        let fd: i32 = self.raw_fd();
+        command.env("LISTEN_FDS", "1");
+        unsafe {
+            command.pre_exec(move || {
+                // dup2 clears close-on-exec, except when the fds are the same
+                let ret: i32 = match fd {
+                    SD_LISTEN_FDS_START => fcntl(fd, F_SETFD, 0),
+                    _ => dup2(fd, SD_LISTEN_FDS_START),
+                };
+                match ret {
+                    -1 => Err(io::Error::last_os_error()),
+                    _ => Ok(()),
+                }
+            });
+        }
+    }
+}
+
+impl Drop for ListenSocket {
+    fn drop(&mut self) {
+        if let ListenSocket::Unix(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
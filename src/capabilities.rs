@@ -0,0 +1,222 @@
+// Capability sets and no_new_privs applied in the child process before exec
+
+use crate::error::Error;
+use crate::job::{tag_setup_error, SetupStep};
+use std::fs;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+// index in the list is the number of the capability
+pub const CAPABILITY_NAMES: [&str; 41] = [
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+];
+
+const PR_CAPBSET_DROP: i32 = 24;
+const PR_SET_NO_NEW_PRIVS: i32 = 38;
+const PR_CAP_AMBIENT: i32 = 47;
+const PR_CAP_AMBIENT_RAISE: u64 = 2;
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+const EINVAL: i32 = 22;
+const EPERM: i32 = 1;
+
+extern "C" {
+    fn prctl(option: i32, arg2: u64, ...) -> i32;
+    fn capget(header: *mut CapHeader, data: *mut CapData) -> i32;
+    fn capset(header: *mut CapHeader, data: *const CapData) -> i32;
+}
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+// `cap_net_raw`, `CAP_NET_RAW` and `net_raw` are the same capability
+pub fn capability_number(name: &str) -> Option<u64> {
+    let name: String = name.to_uppercase();
+    let name: String = match name.starts_with("CAP_") {
+        true => name,
+        false => format!("CAP_{name}"),
+    };
+    CAPABILITY_NAMES
+        .iter()
+        .position(|cap_name| *cap_name == name)
+        .map(|number| number as u64)
+}
+
+#[derive(Debug, Default)]
+pub struct Capabilities {
+    // bounding, permitted and effective sets, every capability if None
+    pub keep: Option<Vec<u64>>,
+    pub ambient: Vec<u64>,
+    pub no_new_privs: bool,
+}
+
+impl Capabilities {
+    pub fn is_enabled(&self) -> bool {
+        self.keep.is_some() || !self.ambient.is_empty() || self.no_new_privs
+    }
+
+    pub fn apply(&self, command: &mut Command) {
+        let keep: Option<u64> = self.keep.as_deref().map(_to_mask);
+        let ambient: u64 = _to_mask(&self.ambient);
+        let no_new_privs: bool = self.no_new_privs;
+        unsafe {
+            let setup = move || -> io::Result<()> {
+                if let Some(keep) = keep {
+                    _drop_bounding_set(keep)?;
+                }
+                if keep.is_some() || ambient != 0 {
+                    _set_capabilities(keep, ambient)?;
+                }
+                for cap in 0..64 {
+                    if ambient & (1 << cap) != 0 {
+                        _check(prctl(
+                            PR_CAP_AMBIENT,
+                            PR_CAP_AMBIENT_RAISE,
+                            cap as u64,
+                            0u64,
+                            0u64,
+                        ))?;
+                    }
+                }
+                if no_new_privs {
+                    _check(prctl(PR_SET_NO_NEW_PRIVS, 1, 0u64, 0u64, 0u64))?;
+                }
+                Ok(())
+            };
+            command
+                .pre_exec(move || setup().map_err(|e| tag_setup_error(SetupStep::Capabilities, e)));
+        }
+    }
+}
+
+pub fn capabilities_error(job_name: &String, e: io::Error) -> Error {
+    match e.raw_os_error() {
+        Some(EPERM) => Error::StartJobFail(format!(
+            "{job_name}: capabilities and ambient_capabilities need CAP_SETPCAP, \
+             run taskmaster as root: {e}"
+        )),
+        _ => Error::StartJobFail(e.to_string()),
+    }
+}
+
+// `CapEff` of /proc/<pid>/status as a list of names
+pub fn effective_capabilities(pid: u32) -> Option<String> {
+    let status: String = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    let raw_mask: &str = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?
+        .trim();
+    let mask: u64 = u64::from_str_radix(raw_mask, 16).ok()?;
+    let names: Vec<String> = CAPABILITY_NAMES
+        .iter()
+        .enumerate()
+        .filter(|(number, _)| mask & (1 << number) != 0)
+        .map(|(_, name)| name.to_lowercase())
+        .collect();
+    Some(match names.is_empty() {
+        true => String::from("none"),
+        false => names.join(","),
+    })
+}
+
+// Private
+
+fn _to_mask(caps: &[u64]) -> u64 {
+    caps.iter().fold(0, |mask, cap| mask | (1 << cap))
+}
+
+fn _check(ret: i32) -> io::Result<()> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+unsafe fn _drop_bounding_set(keep: u64) -> io::Result<()> {
+    for cap in 0..64 {
+        if keep & (1 << cap) != 0 {
+            continue;
+        }
+        if prctl(PR_CAPBSET_DROP, cap as u64, 0u64, 0u64, 0u64) == -1 {
+            let error = io::Error::last_os_error();
+            // past the last capability of the kernel
+            if error.raw_os_error() == Some(EINVAL) {
+                return Ok(());
+            }
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+// Restrict the permitted and effective sets to `keep`, and add the ambient
+// capabilities to the inheritable set, which they must be part of
+unsafe fn _set_capabilities(keep: Option<u64>, ambient: u64) -> io::Result<()> {
+    let mut header: CapHeader = CapHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data: [CapData; 2] = [CapData::default(); 2];
+    _check(capget(&mut header, data.as_mut_ptr()))?;
+    for (i, data) in data.iter_mut().enumerate() {
+        let shift: u32 = 32 * i as u32;
+        if let Some(keep) = keep {
+            data.effective &= (keep >> shift) as u32;
+            data.permitted &= (keep >> shift) as u32;
+            data.inheritable &= (keep >> shift) as u32;
+        }
+        data.inheritable |= (ambient >> shift) as u32;
+    }
+    _check(capset(&mut header, data.as_ptr()))
+}
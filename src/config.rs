@@ -1,7 +1,13 @@
-use crate::parse::parse_job;
+use crate::eventlistener::process_state_event;
+use crate::job::{ProcessInfo, ProcessStates, StateChange};
+use crate::notify::{parse_notify_rule, Notifier, NotifyRule, ProcessEvent};
+use crate::parse::{parse_daemon_options, parse_event_listener, parse_group, parse_job};
+use crate::reaper::{reap_orphans, set_child_subreaper};
+use crate::state::{load_state, process_start_time, save_state, serialize_state, StateEntry};
 use crate::{log, Error, Job, Result};
 use configparser::ini::Ini;
 use std::collections::HashMap;
+use std::path::Path;
 
 pub type ConfigParserContent = HashMap<String, HashMap<String, Option<String>>>;
 pub type RawConfig = HashMap<String, Option<String>>;
@@ -9,18 +15,73 @@ pub type RawConfig = HashMap<String, Option<String>>;
 #[derive(Debug, Clone)]
 pub struct Config {
     map: HashMap<String, Job>,
+    // last content written to the state file
+    saved_state: String,
+    notifier: Notifier,
+    // serial of the last event sent to the event listeners
+    event_serial: u64,
+    // `[group:name]` sections: jobs of each group
+    groups: HashMap<String, Vec<String>>,
+    // `[taskmasterd]` section
+    subreaper: bool,
+    // jobs removed or replaced by a reload, until their processes are stopped
+    retired_jobs: Vec<(String, Job)>,
+    // replacements started once the retired jobs of the same name are gone
+    pending_starts: Vec<String>,
+    // content of the last config file that was successfully applied
+    applied_content: ConfigParserContent,
 }
 
 impl Config {
     pub fn new() -> Self {
         Config {
             map: HashMap::new(),
+            saved_state: String::new(),
+            notifier: Notifier::default(),
+            event_serial: 0,
+            groups: HashMap::new(),
+            subreaper: false,
+            retired_jobs: Vec::new(),
+            pending_starts: Vec::new(),
+            applied_content: ConfigParserContent::new(),
         }
     }
 
     pub fn jobs_routine(&mut self) {
-        for (job_name, job) in self.map.iter_mut() {
+        let mut changes: Vec<(String, usize, StateChange)> = Vec::new();
+        let retired_jobs = self
+            .retired_jobs
+            .iter_mut()
+            .map(|(name, job)| (&*name, job));
+        for (job_name, job) in self.map.iter_mut().chain(retired_jobs) {
             job.processes_routine(job_name);
+            for (process_index, process) in job.processes.iter_mut().enumerate() {
+                for change in process.events.drain(..) {
+                    changes.push((job_name.clone(), process_index, change));
+                }
+            }
+        }
+        for (job_name, process_index, change) in changes {
+            self.notifier.notify(&ProcessEvent {
+                job_name: job_name.clone(),
+                process_index,
+                from_state: change.from_state,
+                state: change.state,
+                pid: change.pid,
+                at: change.at,
+            });
+            self._send_to_event_listeners(&job_name, process_index, &change);
+        }
+        self.retired_jobs.retain(|(_, job)| !job.is_retired());
+        self._start_replacements();
+        self.notifier.routine();
+        if self.subreaper {
+            let mut tracked_pids: Vec<u32> = self.notifier.child_pids();
+            let retired_jobs = self.retired_jobs.iter().map(|(_, job)| job);
+            for job in self.map.values().chain(retired_jobs) {
+                tracked_pids.extend(job.pids());
+            }
+            reap_orphans(&tracked_pids);
         }
     }
 
@@ -28,18 +89,173 @@ impl Config {
         self.map.get_mut(key)
     }
 
-    pub fn contains_key(&mut self, key: &String) -> bool {
-        self.map.contains_key(key)
+    // Sorted names of the jobs
+    pub fn job_names(&self) -> Vec<String> {
+        let mut job_names: Vec<String> = self.map.keys().cloned().collect();
+        job_names.sort();
+        job_names
+    }
+
+    pub fn group_members(&self, group_name: &str) -> Option<&Vec<String>> {
+        self.groups.get(group_name)
+    }
+
+    // Number of processes of the group in each state
+    pub fn group_status(&self, group_name: &String) -> String {
+        let mut counts: Vec<(ProcessStates, usize)> = Vec::new();
+        for job_name in self.groups.get(group_name).into_iter().flatten() {
+            let Some(job) = self.map.get(job_name) else {
+                continue;
+            };
+            for process in job.processes.iter().take(job.num_procs as usize) {
+                match counts.iter_mut().find(|(state, _)| *state == process.state) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((process.state, 1)),
+                }
+            }
+        }
+        let mut ret: String = format!("\nGroup: {group_name}");
+        for (state, count) in counts {
+            ret = format!("{ret} | {state:?}: {count}");
+        }
+        format!("{ret}\n")
+    }
+
+    // Become the parent of the orphaned descendants of the jobs, so the
+    // daemons started by forking commands can be waited for
+    pub fn apply_subreaper(&self) -> Result<()> {
+        set_child_subreaper(self.subreaper)
     }
 
     pub fn run_autostart_jobs(&mut self) {
         for entry in self.map.iter_mut() {
             let job_name: &String = entry.0;
             let job: &mut Job = entry.1;
-            if job.auto_start {
-                let _ = job.start(job_name, None);
+            if !job.auto_start {
+                continue;
+            }
+            // the adopted processes of the retired job are stopped first
+            if self.retired_jobs.iter().any(|(name, _)| name == job_name) {
+                self.pending_starts.push(job_name.clone());
+                continue;
+            }
+            let _ = job.start(job_name, None);
+        }
+    }
+
+    // Re-adopt the processes of a previous taskmaster instance that are still
+    // alive, so they are not started a second time.
+    pub fn adopt_processes(&mut self, state_file: &str) {
+        for entry in load_state(state_file) {
+            let job: &mut Job = if entry.retired {
+                self._retired_job(&entry.job_name, entry.index)
+            } else {
+                match self.map.get_mut(&entry.job_name) {
+                    Some(job) if entry.index < job.num_procs as usize => job,
+                    _ => continue,
+                }
+            };
+            if process_start_time(entry.pid) != Some(entry.start_time) {
+                log(&format!(
+                    "INFO: {}:{} pid {} is gone, can't adopt it",
+                    entry.job_name, entry.index, entry.pid
+                ));
+                continue;
+            }
+            if entry.retired {
+                // RUNNING, so that the retirement sends it the stop signal
+                job.adopt_process(
+                    &entry.job_name,
+                    entry.index,
+                    entry.pid,
+                    entry.start_time,
+                    ProcessStates::Running,
+                );
+                log(&format!(
+                    "INFO: {}:{} adopted pid {} of a retired job, it is stopped",
+                    entry.job_name, entry.index, entry.pid
+                ));
+                continue;
+            }
+            job.adopt_process(
+                &entry.job_name,
+                entry.index,
+                entry.pid,
+                entry.start_time,
+                entry.state,
+            );
+            log(&format!(
+                "INFO: {}:{} adopted pid {} in {:?} state",
+                entry.job_name, entry.index, entry.pid, entry.state
+            ));
+        }
+        for (job_name, job) in self.retired_jobs.iter_mut() {
+            job.retire(job_name);
+        }
+    }
+
+    // Retired job holding the adopted processes of a retired job of the
+    // previous instance. Its config is not known anymore, the processes are
+    // stopped with the default stop signal and stopwaitsecs.
+    fn _retired_job(&mut self, job_name: &String, index: usize) -> &mut Job {
+        let position: usize = match self
+            .retired_jobs
+            .iter()
+            .position(|(name, _)| name == job_name)
+        {
+            Some(position) => position,
+            None => {
+                self.retired_jobs.push((job_name.clone(), Job::default()));
+                self.retired_jobs.len() - 1
+            }
+        };
+        let job: &mut Job = &mut self.retired_jobs[position].1;
+        if job.processes.len() <= index {
+            job.processes.resize_with(index + 1, ProcessInfo::default);
+        }
+        job.num_procs = job.processes.len() as u32;
+        job
+    }
+
+    pub fn save_state(&mut self, state_file: &str) -> Result<()> {
+        let mut entries: Vec<StateEntry> = Vec::new();
+        let jobs = self
+            .map
+            .iter()
+            .map(|(job_name, job)| (job_name, job, false));
+        let retired_jobs = self
+            .retired_jobs
+            .iter()
+            .map(|(job_name, job)| (job_name, job, true));
+        for (job_name, job, retired) in jobs.chain(retired_jobs) {
+            for (index, process) in job.processes.iter().enumerate() {
+                let alive: bool = matches!(
+                    process.state,
+                    ProcessStates::Starting | ProcessStates::Running | ProcessStates::Stopping
+                );
+                if let (true, Some(pid), Some(start_time)) =
+                    (alive, process.pid(), process.start_time)
+                {
+                    entries.push(StateEntry {
+                        job_name: job_name.clone(),
+                        index,
+                        pid,
+                        start_time,
+                        state: process.state,
+                        retired,
+                    });
+                }
             }
         }
+        entries.sort_by(|a, b| {
+            (&a.job_name, a.retired, a.index).cmp(&(&b.job_name, b.retired, b.index))
+        });
+        let content: String = serialize_state(&entries);
+        if content != self.saved_state {
+            save_state(state_file, &content)?;
+            self.saved_state = content;
+        }
+        Ok(())
     }
 
     pub fn status(&mut self) -> String {
@@ -49,59 +265,250 @@ impl Config {
             let job: &mut Job = entry.1;
             ret = format!("{ret}{}", job.status(job_name, None).unwrap());
         }
+        let mut group_names: Vec<&String> = self.groups.keys().collect();
+        group_names.sort();
+        for group_name in group_names {
+            ret = format!("{ret}{}", self.group_status(group_name));
+        }
         ret
     }
 
+    // The running jobs are only touched once the whole file is parsed, so a
+    // bad file leaves the last applied config in place
     pub fn reload_config(&mut self, config_path: &String) -> Result<()> {
         let mut new_config: Config = Config::new();
         new_config.parse_config_file(config_path)?;
-        for (job_name, new_job) in new_config.map.iter_mut() {
-            match self.map.get_mut(job_name) {
-                // job is changed case
-                Some(old_job) if old_job != new_job => {
-                    self.map.get_mut(job_name).unwrap().stop_job_now();
-                    self.map.insert(job_name.clone(), new_job.clone());
-                    if new_job.auto_start {
-                        let job: &mut Job = self.get_mut(job_name).unwrap();
-                        let _ = job.start(job_name, None);
-                    }
-                }
-                // job is the same
-                Some(old_job) if old_job == new_job => continue,
-                // new job case
-                _ => {
-                    self.map.insert(job_name.clone(), new_job.clone());
-                    if new_job.auto_start {
-                        let job: &mut Job = self.get_mut(job_name).unwrap();
-                        let _ = job.start(job_name, None);
-                    }
-                    continue;
-                }
-            };
+        if self.subreaper != new_config.subreaper {
+            new_config.apply_subreaper()?;
+            self.subreaper = new_config.subreaper;
         }
-        // job is deleted
-        for (job_name, _old_job) in self.map.clone().iter() {
-            if new_config.contains_key(job_name) == false {
-                self.map.get_mut(job_name).unwrap().stop_job_now();
-                self.map.remove(&job_name.clone());
-            }
+        self.notifier.rules = new_config.notifier.rules.clone();
+        self.groups = new_config.groups.clone();
+        self.applied_content = new_config.applied_content.clone();
+        for (job_name, _) in self._job_changes(&new_config) {
+            self._update_job(&job_name, new_config.map.get(&job_name));
         }
+        self._start_replacements();
         Ok(())
     }
 
+    // Jobs that a reload would add, change or remove
+    pub fn avail(&self, config_path: &String) -> Result<String> {
+        let mut new_config: Config = Config::new();
+        new_config.parse_config_file(config_path)?;
+        let lines: Vec<String> = self
+            ._job_changes(&new_config)
+            .iter()
+            .map(|(job_name, change)| format!("{job_name}: {}", change.as_str()))
+            .collect();
+        match lines.is_empty() {
+            true => Ok("No job to update".into()),
+            false => Ok(lines.join("\n")),
+        }
+    }
+
+    // Apply the config file to the given jobs only, the other jobs, groups,
+    // notifications and daemon options keep their applied config
+    pub fn update(&mut self, config_path: &String, job_names: &Vec<String>) -> Result<String> {
+        let mut new_config: Config = Config::new();
+        new_config.parse_config_file(config_path)?;
+        let changes: Vec<(String, JobChange)> = self._job_changes(&new_config);
+        let mut lines: Vec<String> = Vec::new();
+        for job_name in job_names {
+            let Some((_, change)) = changes.iter().find(|(name, _)| name == job_name) else {
+                lines.push(match self.map.contains_key(job_name) {
+                    true => format!("{job_name} is up to date"),
+                    false => format!("{job_name}: no such job"),
+                });
+                continue;
+            };
+            self._update_job(job_name, new_config.map.get(job_name));
+            self.applied_content
+                .retain(|section, _| _job_of_section(section) != Some(job_name.as_str()));
+            for (section, entries) in new_config.applied_content.iter() {
+                if _job_of_section(section) == Some(job_name.as_str()) {
+                    self.applied_content
+                        .insert(section.clone(), entries.clone());
+                }
+            }
+            lines.push(format!("{job_name} is {}", change.as_str()));
+        }
+        // the groups keep their applied config, minus the removed jobs
+        for programs in self.groups.values_mut() {
+            programs.retain(|program| self.map.contains_key(program));
+        }
+        self._start_replacements();
+        Ok(lines.join("\n"))
+    }
+
     pub fn parse_config_file(&mut self, config_path: &String) -> Result<()> {
         let mut parser: Ini = Ini::new();
         let cfg: ConfigParserContent = parser
             .load(config_path)
             .map_err(|e| Error::CantLoadFile(e.to_string()))?;
-        self._parse_content_of_parserconfig(cfg)?;
+        self._parse_content_of_parserconfig(cfg.clone())?;
+        self.applied_content = cfg;
+        let config_dir: Option<String> = Path::new(config_path)
+            .canonicalize()
+            .ok()
+            .and_then(|path| Some(path.parent()?.to_string_lossy().into_owned()));
+        for job in self.map.values_mut() {
+            job.config_dir = config_dir.clone();
+        }
         Ok(())
     }
 
+    // Sections and values of the file on disk that differ from the applied
+    // config
+    pub fn config_diff(&self, config_path: &String) -> Result<String> {
+        let on_disk: ConfigParserContent = Ini::new()
+            .load(config_path)
+            .map_err(|e| Error::CantLoadFile(e.to_string()))?;
+        let lines: Vec<String> = diff_config_content(&self.applied_content, &on_disk);
+        match lines.is_empty() {
+            true => Ok(format!("{config_path} is the applied config")),
+            false => Ok(lines.join("\n")),
+        }
+    }
+
+    // Sorted names of the jobs that differ between the running config and
+    // `new_config`
+    fn _job_changes(&self, new_config: &Config) -> Vec<(String, JobChange)> {
+        let mut changes: Vec<(String, JobChange)> = Vec::new();
+        for (job_name, new_job) in new_config.map.iter() {
+            match self.map.get(job_name) {
+                Some(old_job) if old_job == new_job => continue,
+                Some(_) => changes.push((job_name.clone(), JobChange::Changed)),
+                None => changes.push((job_name.clone(), JobChange::Added)),
+            }
+        }
+        for job_name in self.map.keys() {
+            if !new_config.map.contains_key(job_name) {
+                changes.push((job_name.clone(), JobChange::Removed));
+            }
+        }
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+    }
+
+    // Replace the running job by `new_job`, or remove it when it is None
+    fn _update_job(&mut self, job_name: &String, new_job: Option<&Job>) {
+        let Some(new_job) = new_job else {
+            self._retire_job(job_name);
+            return;
+        };
+        match self.map.get_mut(job_name) {
+            // only numprocs is changed
+            Some(old_job) if old_job.eq_except_num_procs(new_job) => {
+                let _ = old_job.scale(job_name, new_job.num_procs);
+            }
+            // job is changed or new
+            _ => {
+                self._retire_job(job_name);
+                self.map.insert(job_name.clone(), new_job.clone());
+                if new_job.auto_start {
+                    self.pending_starts.push(job_name.clone());
+                }
+            }
+        }
+    }
+
+    // Stop the processes of the job in the background, the other jobs keep
+    // being supervised meanwhile
+    fn _retire_job(&mut self, job_name: &String) {
+        let Some(mut job) = self.map.remove(job_name) else {
+            return;
+        };
+        job.retire(job_name);
+        log(&format!("INFO: {job_name} is retired by a reload"));
+        if !job.is_retired() {
+            self.retired_jobs.push((job_name.clone(), job));
+        }
+    }
+
+    // Start the autostarted jobs of a reload once no retired job of the same
+    // name is still stopping
+    fn _start_replacements(&mut self) {
+        let pending_starts: Vec<String> = std::mem::take(&mut self.pending_starts);
+        for job_name in pending_starts {
+            if self.retired_jobs.iter().any(|(name, _)| *name == job_name) {
+                self.pending_starts.push(job_name);
+                continue;
+            }
+            if let Some(job) = self.map.get_mut(&job_name) {
+                let _ = job.start(&job_name, None);
+            }
+        }
+    }
+
+    fn _send_to_event_listeners(
+        &mut self,
+        job_name: &String,
+        process_index: usize,
+        change: &StateChange,
+    ) {
+        let Some(job) = self.map.get(job_name) else {
+            return;
+        };
+        let process_name: String = match job.num_procs {
+            1 => job_name.clone(),
+            _ => format!("{job_name}_{process_index}"),
+        };
+        let (event_name, payload) =
+            process_state_event(&process_name, job_name, change, &job.exit_codes);
+        self.event_serial += 1;
+        for (pool, listener) in self.map.iter_mut() {
+            // a listener is not told about its own processes
+            if pool != job_name {
+                listener.push_event(pool, self.event_serial, &event_name, &payload);
+            }
+        }
+    }
+
     fn _parse_content_of_parserconfig(&mut self, cfg: ConfigParserContent) -> Result<()> {
         for entry in cfg {
             let entry_name: String = entry.0.clone();
-            let job: Job = match parse_job(&entry.1) {
+            if let Some(rule_name) = entry_name.strip_prefix("notify:") {
+                let rule: NotifyRule = parse_notify_rule(&entry.1).map_err(|e| {
+                    log(&format!("ERROR: {e}"));
+                    Error::CantParseEntry {
+                        entry_name: entry_name.clone(),
+                        e: e.to_string(),
+                    }
+                })?;
+                self.notifier.rules.insert(rule_name.to_string(), rule);
+                continue;
+            }
+            if entry_name == "taskmasterd" {
+                self.subreaper = parse_daemon_options(&entry.1).map_err(|e| {
+                    log(&format!("ERROR: {e}"));
+                    Error::CantParseEntry {
+                        entry_name: entry_name.clone(),
+                        e: e.to_string(),
+                    }
+                })?;
+                continue;
+            }
+            if let Some(group_name) = entry_name.strip_prefix("group:") {
+                let programs: Vec<String> = parse_group(&entry.1).map_err(|e| {
+                    log(&format!("ERROR: {e}"));
+                    Error::CantParseEntry {
+                        entry_name: entry_name.clone(),
+                        e: e.to_string(),
+                    }
+                })?;
+                self.groups.insert(group_name.to_string(), programs);
+                continue;
+            }
+            let (job_name, parsed_job) = match entry_name.strip_prefix("eventlistener:") {
+                Some(listener_name) => (listener_name.to_string(), parse_event_listener(&entry.1)),
+                None => (entry_name.clone(), parse_job(&entry.1)),
+            };
+            let parsed_job: Result<Job> = match self.map.contains_key(&job_name) {
+                true => Err(Error::Default(format!("{job_name} is already defined"))),
+                false => parsed_job,
+            };
+            let job: Job = match parsed_job {
                 Err(e) => {
                     log(&format!("ERROR: {e}"));
                     return Err(Error::CantParseEntry {
@@ -111,7 +518,7 @@ impl Config {
                 }
                 Ok(content) => content,
             };
-            self.map.insert(entry_name, job);
+            self.map.insert(job_name, job);
         }
         self.map.remove("default");
         if self
@@ -127,14 +534,121 @@ impl Config {
         if self.map.is_empty() {
             return Err(Error::NoJobEntry);
         }
+        self._check_groups()
+    }
+
+    fn _check_groups(&self) -> Result<()> {
+        for (group_name, programs) in self.groups.iter() {
+            let to_error = |e: String| {
+                log(&format!("ERROR: group:{group_name} {e}"));
+                Err(Error::CantParseEntry {
+                    entry_name: format!("group:{group_name}"),
+                    e,
+                })
+            };
+            if !group_name.chars().all(char::is_alphanumeric) {
+                return Err(Error::JobEntryNameWithNonAlphanumChar);
+            }
+            if self.map.contains_key(group_name) {
+                return to_error(format!("{group_name} is already a job name"));
+            }
+            if let Some(program) = programs.iter().find(|p| !self.map.contains_key(*p)) {
+                return to_error(format!("{program} is not a job"));
+            }
+        }
         Ok(())
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JobChange {
+    Added,
+    Changed,
+    Removed,
+}
+
+impl JobChange {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobChange::Added => "added",
+            JobChange::Changed => "changed",
+            JobChange::Removed => "removed",
+        }
+    }
+}
+
+// Name of the job defined by a section of the config file
+fn _job_of_section(section: &str) -> Option<&str> {
+    if section == "taskmasterd" || section.starts_with("notify:") || section.starts_with("group:") {
+        return None;
+    }
+    Some(section.strip_prefix("eventlistener:").unwrap_or(section))
+}
+
+// `+ [section]` for the added sections, `- [section]` for the removed ones
+// and `~ [section]` followed by the changed keys for the others
+pub fn diff_config_content(old: &ConfigParserContent, new: &ConfigParserContent) -> Vec<String> {
+    let format_value = |value: Option<&Option<String>>| match value {
+        Some(Some(value)) => value.clone(),
+        Some(None) => "(no value)".to_string(),
+        None => "(unset)".to_string(),
+    };
+    let mut sections: Vec<&String> = old.keys().chain(new.keys()).collect();
+    sections.sort();
+    sections.dedup();
+    let mut lines: Vec<String> = Vec::new();
+    for section in sections {
+        let (old_entries, new_entries) = match (old.get(section), new.get(section)) {
+            (None, _) => {
+                lines.push(format!("+ [{section}]"));
+                continue;
+            }
+            (_, None) => {
+                lines.push(format!("- [{section}]"));
+                continue;
+            }
+            (Some(old_entries), Some(new_entries)) => (old_entries, new_entries),
+        };
+        let mut keys: Vec<&String> = old_entries.keys().chain(new_entries.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        let changes: Vec<String> = keys
+            .into_iter()
+            .filter(|key| old_entries.get(*key) != new_entries.get(*key))
+            .map(|key| {
+                format!(
+                    "    {key}: {} -> {}",
+                    format_value(old_entries.get(key)),
+                    format_value(new_entries.get(key))
+                )
+            })
+            .collect();
+        if !changes.is_empty() {
+            lines.push(format!("~ [{section}]"));
+            lines.extend(changes);
+        }
+    }
+    lines
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::job::{AutorestartOptions, StopSignals};
+    use crate::activation::{parse_socket_address, ListenSocket, SocketAddress};
+    use crate::capabilities::{capability_number, effective_capabilities};
+    use crate::eventlistener::{is_subscribed, ListenerEvent, ListenerState};
+    use crate::expand::{expand_variables, validation_lookup};
+    use crate::glob::glob_match;
+    use crate::job::{
+        AutorestartOptions, IoPriorityClass, OperationKind, ReadyOptions, StdinOptions, StopSignals,
+    };
+    use crate::notify::{post_json, NotifyTarget};
+    use crate::parse::{
+        parse_client_input, parse_rolling_from_client_input, parse_stdin_data_from_client_input,
+    };
+    use crate::sdnotify::parse_notify_message;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
 
     #[allow(dead_code)]
     fn get_config_parser_and_config(content: String) -> (ConfigParserContent, Config) {
@@ -331,6 +845,62 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn command_with_quoted_arguments() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(String::from(
+            r#"[test]
+             command = echo "hello world" 'it''s' a\ b "say \"hi\"""#,
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get("test").unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command: "echo".into(),
+                arguments: Some(vec!(
+                    "hello world".into(),
+                    "its".into(),
+                    "a b".into(),
+                    "say \"hi\"".into()
+                )),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn command_unterminated_quote() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(String::from(
+            "[test]
+             command = echo \"hello",
+        ));
+        let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+        assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        assert!(config.map.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn command_with_shell() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(String::from(
+            "[test]
+             command = echo $HOME | wc -c
+             shell = true",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get("test").unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command: "echo $HOME | wc -c".into(),
+                shell: true,
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
     #[test]
     fn num_procs_ok() -> Result<()> {
         let job_name: String = String::from("test");
@@ -916,4 +1486,1631 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn variables_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/test --port=80%(process_num)02d
+             stdout=%(here)s/%(program_name)s-%(process_num)d.log
+             environment=HOME=%(ENV_HOME)s",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command: "/bin/test".into(),
+                arguments: Some(vec!["--port=80%(process_num)02d".into()]),
+                stdout_file: Some("%(here)s/%(program_name)s-%(process_num)d.log".into()),
+                environment: Some(HashMap::from([("HOME".into(), "%(ENV_HOME)s".into())])),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn variables_bad_value() -> Result<()> {
+        let job_name: String = String::from("test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/test
+             stdout=%(unknown)s.log",
+        ));
+        let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+        assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        assert!(config.map.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn environment_isolation_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             clean_environment=true
+             environment_allowlist=PATH, HOME
+             environment_file=%(here)s/.env",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                clean_environment: true,
+                environment_allowlist: Some(vec!["PATH".into(), "HOME".into()]),
+                environment_file: Some("%(here)s/.env".into()),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn environment_allowlist_bad_value() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             environment_allowlist=PATH,,HOME",
+        ));
+        let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+        assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        assert!(config.map.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn environment_file_content() -> Result<()> {
+        let path = std::env::temp_dir().join("taskmaster_environment_file_content.env");
+        std::fs::write(
+            &path,
+            "# comment\n\
+             \n\
+             A=1\n\
+             export B = two words # comment\n\
+             C='single $quoted'\n\
+             D=\"line\\nbreak \\\"quoted\\\"\"\n",
+        )
+        .unwrap();
+        let env = crate::parse::parse_env_file(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            env?,
+            HashMap::from([
+                ("A".into(), "1".into()),
+                ("B".into(), "two words".into()),
+                ("C".into(), "single $quoted".into()),
+                ("D".into(), "line\nbreak \"quoted\"".into()),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stdin_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             stdin=pipe",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                stdin: StdinOptions::Pipe,
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stdin_bad_value() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             stdin=tty",
+        ));
+        let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+        assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        assert!(config.map.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn pty_with_stdin_pipe() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             pty=true
+             stdin=pipe",
+        ));
+        let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+        assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        assert!(config.map.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn watch_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             watch=%(here)s/src/*.py, config.yml
+             watch_debounce_ms=1000",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                watch: Some(vec!["%(here)s/src/*.py".into(), "config.yml".into()]),
+                watch_debounce_ms: 1000,
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn watch_bad_value() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             watch=src/,,config.yml",
+        ));
+        let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+        assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        assert!(config.map.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn hooks_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             pre_start=%(here)s/migrate.sh --all
+             post_stop=rm -rf /tmp/%(program_name)s
+             hook_timeout_secs=30",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                pre_start: Some("%(here)s/migrate.sh --all".into()),
+                post_stop: Some("rm -rf /tmp/%(program_name)s".into()),
+                hook_timeout_secs: 30,
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hook_timeout_bad_value() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             pre_stop=true
+             hook_timeout_secs=-1",
+        ));
+        let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+        assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        assert!(config.map.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn notify_ok() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(String::from(
+            "[test]
+             command=/bin/test
+             [notify:pager]
+             jobs=web*, test
+             states=fatal, BACKOFF
+             url=http://127.0.0.1:8080/events
+             retries=5
+             [notify:script]
+             states=exited
+             command=/usr/local/bin/on-exit",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        assert_eq!(config.map.len(), 1);
+        assert_eq!(
+            config.notifier.rules.get("pager"),
+            Some(&NotifyRule {
+                jobs: vec!["web*".into(), "test".into()],
+                states: vec![ProcessStates::Fatal, ProcessStates::Backoff],
+                target: NotifyTarget::Url("http://127.0.0.1:8080/events".into()),
+                retries: 5,
+            })
+        );
+        assert_eq!(
+            config.notifier.rules.get("script"),
+            Some(&NotifyRule {
+                jobs: vec!["*".into()],
+                states: vec![ProcessStates::Exited],
+                target: NotifyTarget::Command("/usr/local/bin/on-exit".into()),
+                retries: 3,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn notify_bad_value() -> Result<()> {
+        for rule in [
+            "states=fatal",
+            "states=dead\n command=true",
+            "states=fatal\n url=https://example.com/",
+            "states=fatal\n command=true\n url=http://localhost/",
+        ] {
+            let (config_parser, mut config) = get_config_parser_and_config(format!(
+                "[test]
+                 command=/bin/test
+                 [notify:bad]
+                 {}",
+                rule
+            ));
+            let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+            assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn eventlistener_ok() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(String::from(
+            "[test]
+             command=/bin/test
+             [eventlistener:crashmail]
+             command=/usr/local/bin/crashmail -a
+             events=PROCESS_STATE_EXITED, PROCESS_STATE_FATAL
+             buffer_size=50",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get("crashmail").unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command: "/usr/local/bin/crashmail".into(),
+                arguments: Some(vec!["-a".into()]),
+                stdin: StdinOptions::Pipe,
+                events: Some(vec![
+                    "PROCESS_STATE_EXITED".into(),
+                    "PROCESS_STATE_FATAL".into()
+                ]),
+                buffer_size: 50,
+                ..Default::default()
+            },
+        );
+        assert_eq!(config.map.get("test").unwrap().events, None);
+        Ok(())
+    }
+
+    #[test]
+    fn eventlistener_bad_value() -> Result<()> {
+        for listener in [
+            "command=/bin/listener",
+            "command=/bin/listener\n events=PROCESS_STATE_CRASHED",
+            "command=/bin/listener\n events=PROCESS_STATE\n stdout=/tmp/out",
+            "command=/bin/listener\n events=PROCESS_STATE\n buffer_size=0",
+        ] {
+            let (config_parser, mut config) = get_config_parser_and_config(format!(
+                "[eventlistener:test]
+                 {listener}"
+            ));
+            let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+            assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+            assert!(config.map.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn group_ok() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(String::from(
+            "[web]
+             command=/bin/web
+             [worker]
+             command=/bin/worker
+             numprocs=2
+             [group:backend]
+             programs=web, Worker",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        assert_eq!(
+            config.group_members("backend"),
+            Some(&vec!["web".into(), "worker".into()])
+        );
+        let input = parse_client_input(&mut config, &"start backend:*".into())?;
+        assert_eq!(
+            input.targets,
+            vec![Ok(("web".into(), None)), Ok(("worker".into(), None))]
+        );
+        assert_eq!(input.groups, vec![String::from("backend")]);
+        let input = parse_client_input(&mut config, &"stop backend:worker:1".into())?;
+        assert_eq!(input.targets, vec![Ok(("worker".into(), Some(1)))]);
+        let input = parse_client_input(&mut config, &"stop backend:other".into())?;
+        assert!(input.targets[0].is_err());
+        assert_eq!(
+            config.group_status(&"backend".into()),
+            "\nGroup: backend | Stopped: 3\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn group_bad_value() -> Result<()> {
+        for group in [
+            "[group:backend]\n programs=web,,web",
+            "[group:backend]\n programs=web, other",
+            "[group:web]\n programs=web",
+        ] {
+            let (config_parser, mut config) = get_config_parser_and_config(format!(
+                "[web]
+                 command=/bin/web
+                 {group}"
+            ));
+            let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+            assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn client_multiple_targets() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(String::from(
+            "[api]
+             command=/bin/api
+             [webfront]
+             command=/bin/web
+             [webback]
+             command=/bin/web
+             [worker]
+             command=/bin/worker
+             numprocs=3",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let input = parse_client_input(&mut config, &"restart api worker:2 nope web*".into())?;
+        assert_eq!(input.targets.len(), 5);
+        assert_eq!(input.targets[0], Ok(("api".into(), None)));
+        assert_eq!(input.targets[1], Ok(("worker".into(), Some(2))));
+        assert!(input.targets[2].is_err());
+        assert_eq!(input.targets[3], Ok(("webback".into(), None)));
+        assert_eq!(input.targets[4], Ok(("webfront".into(), None)));
+        let input = parse_client_input(&mut config, &"start all".into())?;
+        assert_eq!(input.targets.len(), 4);
+        let input = parse_client_input(&mut config, &"scale worker 5".into())?;
+        assert_eq!(input.targets, vec![Ok(("worker".into(), None))]);
+        assert_eq!(input.extra, Some("5".into()));
+        assert!(parse_client_input(&mut config, &"stop".into()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sandbox_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             private_network=true
+             private_tmp=true
+             private_pid=true
+             read_only_paths=/etc, /srv/%(program_name)s",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                private_network: true,
+                private_tmp: true,
+                private_pid: true,
+                read_only_paths: Some(vec!["/etc".into(), "/srv/%(program_name)s".into()]),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn read_only_paths_bad_value() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             read_only_paths=/etc, data",
+        ));
+        let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+        assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        assert!(config.map.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn capabilities_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             no_new_privs=true
+             capabilities=net_bind_service, CAP_NET_RAW
+             ambient_capabilities=cap_net_bind_service",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                no_new_privs: true,
+                capabilities: Some(vec!["CAP_NET_BIND_SERVICE".into(), "CAP_NET_RAW".into()]),
+                ambient_capabilities: Some(vec!["CAP_NET_BIND_SERVICE".into()]),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn capabilities_bad_value() -> Result<()> {
+        for capabilities in [
+            "capabilities=cap_fly",
+            "capabilities=net_raw\n ambient_capabilities=net_admin",
+            "no_new_privs=maybe",
+        ] {
+            let (config_parser, mut config) = get_config_parser_and_config(format!(
+                "[test]
+                 command=/bin/test
+                 {capabilities}"
+            ));
+            let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+            assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+            assert!(config.map.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn scheduling_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             nice=-5
+             cpu_affinity=0, 2-3
+             ioprio_class=best-effort
+             ioprio_level=6
+             oom_score_adj=500",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                nice: Some(-5),
+                cpu_affinity: Some(vec![0, 2, 3]),
+                ioprio_class: Some(IoPriorityClass::BestEffort),
+                ioprio_level: Some(6),
+                oom_score_adj: Some(500),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn scheduling_bad_value() -> Result<()> {
+        for scheduling in [
+            "nice=30",
+            "cpu_affinity=3-1",
+            "cpu_affinity=2048",
+            "ioprio_class=fast",
+            "ioprio_class=idle\n ioprio_level=3",
+            "ioprio_level=8",
+            "oom_score_adj=2000",
+        ] {
+            let (config_parser, mut config) = get_config_parser_and_config(format!(
+                "[test]
+                 command=/bin/test
+                 {scheduling}"
+            ));
+            let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+            assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+            assert!(config.map.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn subreaper_and_pidfile_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[taskmasterd]
+             subreaper=true
+
+             [{job_name}]
+             command={command}
+             pidfile=/run/test.pid",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        assert!(config.subreaper);
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                pidfile: Some("/run/test.pid".into()),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn subreaper_and_pidfile_bad_value() -> Result<()> {
+        for content in [
+            "[taskmasterd]\n subreaper=maybe\n [test]\n command=/bin/test",
+            "[test]\n command=/bin/test\n pidfile=/run/a b.pid",
+        ] {
+            let (config_parser, mut config) = get_config_parser_and_config(content.into());
+            let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+            assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn ready_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             ready=notify",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                ready: ReadyOptions::Notify,
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ready_bad_value() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(
+            "[test]
+             command=/bin/test
+             ready=whenever"
+                .into(),
+        );
+        let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+        assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+        assert!(config.map.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn watchdog_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             watchdog_secs=30
+             heartbeat_file=/run/test.heartbeat",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                watchdog_secs: 30,
+                heartbeat_file: Some("/run/test.heartbeat".into()),
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn watchdog_bad_value() -> Result<()> {
+        for watchdog in ["watchdog_secs=-1", "heartbeat_file=/run/test.heartbeat"] {
+            let (config_parser, mut config) = get_config_parser_and_config(format!(
+                "[test]
+                 command=/bin/test
+                 {watchdog}"
+            ));
+            let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+            assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+            assert!(config.map.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn socket_ok() -> Result<()> {
+        let job_name: String = String::from("test");
+        let command: String = String::from("/bin/test");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command={command}
+             socket=tcp://127.0.0.1:8000
+             socket_lazy=true",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &Job = config.map.get(&job_name).unwrap();
+        assert_eq!(
+            *job,
+            Job {
+                command,
+                socket: Some(SocketAddress::Tcp("127.0.0.1:8000".into())),
+                socket_lazy: true,
+                ..Default::default()
+            },
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn socket_bad_value() -> Result<()> {
+        for socket in [
+            "socket=127.0.0.1:8000",
+            "socket=unix://relative.sock",
+            "socket_lazy=true",
+            "socket=tcp://127.0.0.1:8000\n socket_lazy=true\n autostart=true",
+        ] {
+            let (config_parser, mut config) = get_config_parser_and_config(format!(
+                "[test]
+                 command=/bin/test
+                 {socket}"
+            ));
+            let val: Result<()> = config._parse_content_of_parserconfig(config_parser);
+            assert!(matches!(val, Err(Error::CantParseEntry { .. })));
+            assert!(config.map.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn client_rolling_restart() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(String::from(
+            "[web]
+             command=/bin/web
+             numprocs=4",
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let input = parse_client_input(&mut config, &"restart --rolling web --batch=2".into())?;
+        assert_eq!(input.targets, vec![Ok(("web".into(), None))]);
+        assert_eq!(parse_rolling_from_client_input(&input.flags), Ok(Some(2)));
+        let input = parse_client_input(&mut config, &"restart --rolling web".into())?;
+        assert_eq!(parse_rolling_from_client_input(&input.flags), Ok(Some(1)));
+        let input = parse_client_input(&mut config, &"restart web".into())?;
+        assert_eq!(parse_rolling_from_client_input(&input.flags), Ok(None));
+        for flags in ["--batch=2", "--rolling --batch=0", "--fast"] {
+            let input = parse_client_input(&mut config, &format!("restart {flags} web"))?;
+            assert!(parse_rolling_from_client_input(&input.flags).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn config_diff() -> Result<()> {
+        let old: ConfigParserContent = Ini::new()
+            .read("[web]\ncommand=a\nnumprocs=2\n[api]\ncommand=b\n".into())
+            .unwrap();
+        let new: ConfigParserContent = Ini::new()
+            .read("[web]\ncommand=c\nautostart=true\n[api]\ncommand=b\n[db]\ncommand=d\n".into())
+            .unwrap();
+        assert_eq!(
+            diff_config_content(&old, &new),
+            vec![
+                "+ [db]",
+                "~ [web]",
+                "    autostart: (unset) -> true",
+                "    command: a -> c",
+                "    numprocs: 2 -> (unset)",
+            ]
+        );
+        assert_eq!(diff_config_content(&new, &old)[0], "- [db]");
+        assert!(diff_config_content(&old, &old).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn job_changes() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(
+            "[web]\ncommand=a\n[api]\ncommand=b\n[db]\ncommand=d\n".into(),
+        );
+        config._parse_content_of_parserconfig(config_parser)?;
+        let (config_parser, mut new_config) = get_config_parser_and_config(
+            "[web]\ncommand=c\n[api]\ncommand=b\n[cache]\ncommand=e\n".into(),
+        );
+        new_config._parse_content_of_parserconfig(config_parser)?;
+        assert_eq!(
+            config._job_changes(&new_config),
+            vec![
+                ("cache".to_string(), JobChange::Added),
+                ("db".to_string(), JobChange::Removed),
+                ("web".to_string(), JobChange::Changed),
+            ]
+        );
+        assert!(config._job_changes(&config.clone()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn scale_up_while_stopping() -> Result<()> {
+        let job_name: String = String::from("sleeper");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sleep 60
+             numprocs=2
+             startsecs=0"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        let old_pid: Option<u32> = job.processes[1].pid();
+        job.scale(&job_name, 1)?;
+        assert_eq!(job.processes[1].state, ProcessStates::Stopping);
+        job.scale(&job_name, 2)?;
+        for _ in 0..100 {
+            job.processes_routine(&job_name);
+            if job.processes[1].state != ProcessStates::Stopping {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let new_pid: Option<u32> = job.processes[1].pid();
+        let restarted: bool = job.processes[1].state == ProcessStates::Starting;
+        job.stop(&job_name, None)?;
+        while job
+            .processes
+            .iter()
+            .any(|p| p.state == ProcessStates::Stopping)
+        {
+            job.processes_routine(&job_name);
+        }
+        assert!(restarted);
+        assert!(new_pid.is_some() && new_pid != old_pid);
+        Ok(())
+    }
+
+    #[test]
+    fn client_stdin_data() {
+        let data = |raw: &str| parse_stdin_data_from_client_input(&Some(raw.into()));
+        assert_eq!(data("hello world"), "hello world\n");
+        assert_eq!(data("a\\tb\\nc"), "a\tb\nc\n");
+        assert_eq!(data("no line feed\\c"), "no line feed");
+        assert_eq!(data("a\\\\c"), "a\\c\n");
+        assert_eq!(data("\\x"), "\\x\n");
+        assert_eq!(parse_stdin_data_from_client_input(&None), "\n");
+    }
+
+    #[test]
+    fn write_stdin_short_write() -> Result<()> {
+        let job_name: String = String::from("sleeper");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sleep 60
+             stdin=pipe"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        let data: String = "x".repeat(60000);
+        let first = job.write_stdin(&job_name, None, &data);
+        let second = job.write_stdin(&job_name, None, &data);
+        let buffered: usize = job.processes[0].stdin_buffer.len();
+        let third = job.write_stdin(&job_name, None, &data);
+        job.stop(&job_name, None)?;
+        while job.processes[0].state == ProcessStates::Stopping {
+            job.processes_routine(&job_name);
+        }
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(Error::StdinJobFail(ref e)) if e.contains("has read")));
+        assert!(buffered > 0 && buffered < data.len());
+        assert!(matches!(third, Err(Error::StdinJobFail(ref e)) if e.contains("doesn't read")));
+        Ok(())
+    }
+
+    #[test]
+    fn pre_hooks_dont_block() -> Result<()> {
+        let job_name: String = String::from("sleeper");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sleep 60
+             startsecs=0
+             pre_start=sleep 0.3
+             pre_stop=sleep 0.3"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        let wait_for = |job: &mut Job, state: ProcessStates| {
+            let started_at = std::time::Instant::now();
+            while job.processes[0].state != state && started_at.elapsed().as_secs() < 5 {
+                job.processes_routine(&job_name);
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            started_at.elapsed()
+        };
+        let started_at = std::time::Instant::now();
+        job.start(&job_name, None)?;
+        assert!(started_at.elapsed().as_millis() < 200);
+        assert_eq!(job.processes[0].state, ProcessStates::Stopped);
+        assert!(wait_for(job, ProcessStates::Running).as_millis() >= 200);
+        let started_at = std::time::Instant::now();
+        job.stop(&job_name, None)?;
+        assert!(started_at.elapsed().as_millis() < 200);
+        assert_eq!(job.processes[0].state, ProcessStates::Stopping);
+        assert!(wait_for(job, ProcessStates::Stopped).as_millis() >= 200);
+        Ok(())
+    }
+
+    #[test]
+    fn spawn_error_of_failed_step() -> Result<()> {
+        let job_name: String = String::from("pinned");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/true
+             no_new_privs=true
+             cpu_affinity=1023"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        let ret = job.start(&job_name, None);
+        assert!(matches!(ret, Err(Error::StartJobFail(ref e)) if e.contains("cpu_affinity")));
+        Ok(())
+    }
+
+    #[test]
+    fn notify_socket_after_autorestart() -> Result<()> {
+        let job_name: String = String::from("notifier");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sleep 0.2
+             startsecs=0
+             autorestart=always
+             watchdog_secs=60"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        let first_pid: Option<u32> = job.processes[0].pid();
+        let started_at = std::time::Instant::now();
+        while job.processes[0].pid() == first_pid && started_at.elapsed().as_secs() < 5 {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let socket_exists: bool = job.processes[0]
+            .notify_socket
+            .as_ref()
+            .is_some_and(|socket| socket.path.exists());
+        job.stop(&job_name, None)?;
+        while job.processes[0].state == ProcessStates::Stopping {
+            job.processes_routine(&job_name);
+        }
+        assert!(job.processes[0].pid() != first_pid);
+        assert!(socket_exists);
+        Ok(())
+    }
+
+    #[test]
+    fn listen_fds_with_clean_environment() -> Result<()> {
+        let job_name: String = String::from("activated");
+        let output =
+            std::env::temp_dir().join(format!("taskmaster_listen_fds_{}", std::process::id()));
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sh -c \"echo $LISTEN_FDS > {}\"
+             startsecs=0
+             clean_environment=true
+             socket=tcp://127.0.0.1:0",
+            output.display()
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        let started_at = std::time::Instant::now();
+        while job.processes[0].state != ProcessStates::Exited && started_at.elapsed().as_secs() < 5
+        {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let listen_fds: String = std::fs::read_to_string(&output).unwrap_or_default();
+        let _ = std::fs::remove_file(&output);
+        assert_eq!(listen_fds, "1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn rolling_restart_result() -> Result<()> {
+        let (config_parser, mut config) = get_config_parser_and_config(
+            "[web]
+             command=/bin/sleep 60
+             numprocs=2
+             startsecs=0
+
+             [broken]
+             command=/bin/false
+             numprocs=2
+             startsecs=1
+             startretries=0"
+                .to_string(),
+        );
+        config._parse_content_of_parserconfig(config_parser)?;
+        for (job_name, expected) in [
+            ("web", Ok("web is restarted successfully!".to_string())),
+            (
+                "broken",
+                Err(Error::StartJobFail(
+                    "broken:0 of the batch [0] is FATAL, rolling restart is aborted".into(),
+                )),
+            ),
+        ] {
+            let job_name: String = job_name.to_string();
+            let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+            let id: u64 = job.rolling_restart(&job_name, None, 1)?;
+            assert!(job.rolling_restart(&job_name, None, 1).is_err());
+            let started_at = std::time::Instant::now();
+            let mut result: Option<Result<String>> = None;
+            while result.is_none() && started_at.elapsed().as_secs() < 10 {
+                job.processes_routine(&job_name);
+                result = job.operation_result(id);
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            assert_eq!(result, Some(expected));
+            let _ = job.stop(&job_name, None);
+            while job
+                .processes
+                .iter()
+                .any(|process| process.state == ProcessStates::Stopping)
+            {
+                job.processes_routine(&job_name);
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn retire_with_pre_stop_hook() -> Result<()> {
+        let job_name: String = String::from("sleeper");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sleep 60
+             startsecs=0
+             pre_stop=sleep 0.3"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        let started_at = std::time::Instant::now();
+        while job.processes[0].state != ProcessStates::Running && started_at.elapsed().as_secs() < 5
+        {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let started_at = std::time::Instant::now();
+        job.retire(&job_name);
+        assert!(started_at.elapsed().as_millis() < 200);
+        assert_eq!(job.processes[0].state, ProcessStates::Stopping);
+        while !job.processes.is_empty() && started_at.elapsed().as_secs() < 5 {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(job.processes.is_empty());
+        assert!(started_at.elapsed().as_millis() >= 200);
+        Ok(())
+    }
+
+    #[test]
+    fn update_removes_group_member() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("taskmaster_update_{}.ini", std::process::id()));
+        let config_path: String = path.to_string_lossy().into_owned();
+        std::fs::write(
+            &path,
+            "[web]\ncommand=/bin/web\n[api]\ncommand=/bin/api\n[group:g]\nprograms=web,api\n",
+        )
+        .unwrap();
+        let mut config: Config = Config::new();
+        config.parse_config_file(&config_path)?;
+        std::fs::write(&path, "[api]\ncommand=/bin/api\n[group:g]\nprograms=api\n").unwrap();
+        let updated = config.update(&config_path, &vec!["web".into()]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(updated?, "web is removed");
+        assert_eq!(config.group_members("g"), Some(&vec!["api".into()]));
+        let input = parse_client_input(&mut config, &"start g:*".into())?;
+        assert_eq!(input.targets, vec![Ok(("api".into(), None))]);
+        Ok(())
+    }
+
+    #[test]
+    fn rolling_restart_with_pre_start() -> Result<()> {
+        let job_name: String = String::from("web");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sleep 60
+             numprocs=2
+             startsecs=0
+             pre_start=sleep 0.2"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        let id: u64 = job.rolling_restart(&job_name, None, 1)?;
+        let started_at = std::time::Instant::now();
+        let mut result: Option<Result<String>> = None;
+        while result.is_none() && started_at.elapsed().as_secs() < 5 {
+            job.processes_routine(&job_name);
+            result = job.operation_result(id);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(
+            result,
+            Some(Ok("web is restarted successfully!".to_string()))
+        );
+        assert!(started_at.elapsed().as_millis() >= 400);
+        let _ = job.stop(&job_name, None);
+        while job
+            .processes
+            .iter()
+            .any(|process| process.state == ProcessStates::Stopping)
+        {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stop_during_backoff() -> Result<()> {
+        let job_name: String = String::from("bad");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/false
+             startsecs=1
+             startretries=5"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        let started_at = std::time::Instant::now();
+        while job.processes[0].state != ProcessStates::Backoff && started_at.elapsed().as_secs() < 5
+        {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let ids: Vec<u64> = job.queue_operation(&job_name, None, OperationKind::Stop)?;
+        assert_eq!(
+            job.operation_result(ids[0]),
+            Some(Ok("bad:0 is stopped".to_string()))
+        );
+        let stopped_at = std::time::Instant::now();
+        while stopped_at.elapsed().as_millis() < 1500 {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(job.processes[0].state, ProcessStates::Stopped);
+        Ok(())
+    }
+
+    #[test]
+    fn hook_timeout_kills_its_children() -> Result<()> {
+        let job_name: String = String::from("slow");
+        let pid_file =
+            std::env::temp_dir().join(format!("taskmaster_hook_child_{}", std::process::id()));
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sleep 60
+             startretries=0
+             pre_start=sleep 30 & echo $! > {} && wait
+             hook_timeout_secs=1",
+            pid_file.display()
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        let started_at = std::time::Instant::now();
+        while job.processes[0].state != ProcessStates::Fatal && started_at.elapsed().as_secs() < 5 {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let pid: String = std::fs::read_to_string(&pid_file).unwrap_or_default();
+        let _ = std::fs::remove_file(&pid_file);
+        let is_alive = || match std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())) {
+            // a zombie waits for the subreaper of another test
+            Ok(stat) => stat.split_whitespace().nth(2) != Some("Z"),
+            Err(_) => false,
+        };
+        let killed_at = std::time::Instant::now();
+        while is_alive() && killed_at.elapsed().as_secs() < 1 {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(job.processes[0].state, ProcessStates::Fatal);
+        assert!(pid.trim().parse::<u32>().is_ok());
+        assert!(!is_alive());
+        Ok(())
+    }
+
+    #[test]
+    fn retired_job_waits_for_its_hooks() -> Result<()> {
+        let job_name: String = String::from("web");
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sleep 60
+             startsecs=0
+             post_stop=sleep 0.3"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        while job.processes[0].state != ProcessStates::Running {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let retired_at = std::time::Instant::now();
+        config._retire_job(&job_name);
+        while !config.retired_jobs.is_empty() && retired_at.elapsed().as_secs() < 5 {
+            config.jobs_routine();
+            if let Some((_, job)) = config.retired_jobs.first() {
+                assert!(!job.processes.is_empty() || !job.running_hooks.is_empty());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(config.retired_jobs.is_empty());
+        assert!(retired_at.elapsed().as_millis() >= 300);
+        Ok(())
+    }
+
+    #[test]
+    fn listener_event_bigger_than_pipe() -> Result<()> {
+        let job_name: String = String::from("listener");
+        let prefix =
+            std::env::temp_dir().join(format!("taskmaster_listener_{}", std::process::id()));
+        let script: String = format!("{}.sh", prefix.display());
+        let output: String = format!("{}.out", prefix.display());
+        // doesn't read its stdin before the pipe is full
+        std::fs::write(
+            &script,
+            format!(
+                "printf 'READY\\n'\nsleep 0.5\nread header\nhead -c \"${{header##*len:}}\" > {output}\nprintf 'RESULT 2\\nOK'\nexec sleep 60\n"
+            ),
+        )
+        .unwrap();
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[eventlistener:{job_name}]
+             command=/bin/sh {script}
+             startsecs=0
+             events=PROCESS_STATE"
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        let payload: String = format!("processname:web {}", "x".repeat(200_000));
+        job.push_event(&job_name, 1, "PROCESS_STATE_RUNNING", &payload);
+        let started_at = std::time::Instant::now();
+        while started_at.elapsed().as_secs() < 5 {
+            job.processes_routine(&job_name);
+            let listener = job.processes[0].listener.as_ref();
+            if listener.is_some_and(|listener| listener.state == ListenerState::Acknowledged)
+                && job.event_queue.is_empty()
+                && started_at.elapsed().as_millis() > 500
+            {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let received: String = std::fs::read_to_string(&output).unwrap_or_default();
+        let _ = std::fs::remove_file(&script);
+        let _ = std::fs::remove_file(&output);
+        let _ = job.stop(&job_name, None);
+        while job.processes[0].state == ProcessStates::Stopping {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(received == payload);
+        Ok(())
+    }
+
+    #[test]
+    fn write_stdin_without_pipe() -> Result<()> {
+        let job_name: String = String::from("reader");
+        let prefix = std::env::temp_dir().join(format!("taskmaster_stdin_{}_", std::process::id()));
+        let (config_parser, mut config) = get_config_parser_and_config(format!(
+            "[{job_name}]
+             command=/bin/sh -c \"head -c 6 > {}%(process_num)d\"
+             numprocs=2
+             stdin=pipe",
+            prefix.display()
+        ));
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        job.close_stdin(&job_name, Some(0))?;
+        let written = job.write_stdin(&job_name, None, &"hello\n".to_string());
+        let output: String = format!("{}1", prefix.display());
+        let started_at = std::time::Instant::now();
+        while std::fs::read_to_string(&output).unwrap_or_default().len() < 6
+            && started_at.elapsed().as_secs() < 2
+        {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let received: String = std::fs::read_to_string(&output).unwrap_or_default();
+        let _ = job.stop(&job_name, None);
+        while job
+            .processes
+            .iter()
+            .any(|process| process.state == ProcessStates::Stopping)
+        {
+            job.processes_routine(&job_name);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let _ = std::fs::remove_file(format!("{}0", prefix.display()));
+        let _ = std::fs::remove_file(&output);
+        assert_eq!(
+            written,
+            Err(Error::StdinJobFail("reader:0 has no stdin pipe".into()))
+        );
+        assert_eq!(received, "hello\n");
+        Ok(())
+    }
+
+    #[test]
+    fn retired_processes_in_state_file() -> Result<()> {
+        let job_name: String = String::from("web");
+        let state_file =
+            std::env::temp_dir().join(format!("taskmaster_retired_{}.state", std::process::id()));
+        let state_file: &str = state_file.to_str().unwrap();
+        let content: String = format!(
+            "[{job_name}]
+             command=/bin/sleep 60
+             startsecs=0
+             pre_stop=sleep 0.5"
+        );
+        let (config_parser, mut config) = get_config_parser_and_config(content.clone());
+        config._parse_content_of_parserconfig(config_parser)?;
+        let job: &mut Job = config.map.get_mut(&job_name).unwrap();
+        job.start(&job_name, None)?;
+        let pid: u32 = job.processes[0].pid().unwrap();
+        config._retire_job(&job_name);
+        config.save_state(state_file)?;
+        let saved: String = std::fs::read_to_string(state_file).unwrap_or_default();
+        let (config_parser, mut new_config) = get_config_parser_and_config(content);
+        new_config._parse_content_of_parserconfig(config_parser)?;
+        new_config.adopt_processes(state_file);
+        let _ = std::fs::remove_file(state_file);
+        assert!(
+            saved.starts_with(&format!("web 0 {pid} ")) && saved.ends_with(" Stopping retired\n")
+        );
+        assert_eq!(new_config.retired_jobs[0].1.processes[0].pid(), Some(pid));
+        assert_eq!(new_config.map[&job_name].processes[0].pid(), None);
+        let started_at = std::time::Instant::now();
+        while !new_config.retired_jobs.is_empty() && started_at.elapsed().as_secs() < 5 {
+            new_config.jobs_routine();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(new_config.retired_jobs.is_empty());
+        assert_eq!(process_start_time(pid), None);
+        Ok(())
+    }
+
+    #[test]
+    fn state_line_round_trip() {
+        let entry = StateEntry {
+            job_name: "web".into(),
+            index: 2,
+            pid: 4242,
+            start_time: 123456,
+            state: ProcessStates::Running,
+            retired: false,
+        };
+        assert_eq!(StateEntry::from_line(&entry.to_line()), Some(entry));
+        assert_eq!(StateEntry::from_line("web 2 4242 123456 Exited"), None);
+        assert_eq!(StateEntry::from_line("web 2 notapid 123456 Running"), None);
+    }
+
+    #[test]
+    fn start_time_of_current_process() {
+        let pid: u32 = std::process::id();
+        assert!(process_start_time(pid).is_some());
+        assert_eq!(process_start_time(pid), process_start_time(pid));
+    }
+
+    fn expand_lookup(name: &str) -> Option<String> {
+        match name {
+            "process_num" => Some("3".into()),
+            "program_name" => Some("web".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn expand_ok() {
+        assert_eq!(
+            expand_variables(
+                "/var/log/%(program_name)s-%(process_num)02d.log",
+                expand_lookup
+            ),
+            Ok("/var/log/web-03.log".into())
+        );
+        assert_eq!(
+            expand_variables("--port=80%(process_num)d 100%%", expand_lookup),
+            Ok("--port=803 100%".into())
+        );
+        assert_eq!(
+            expand_variables("no variable", expand_lookup),
+            Ok("no variable".into())
+        );
+    }
+
+    #[test]
+    fn expand_bad_format() {
+        assert!(expand_variables("%(unknown)s", expand_lookup).is_err());
+        assert!(expand_variables("%(process_num", expand_lookup).is_err());
+        assert!(expand_variables("%(process_num)x", expand_lookup).is_err());
+        assert!(expand_variables("%(program_name)d", expand_lookup).is_err());
+        assert!(expand_variables("50%", expand_lookup).is_err());
+        assert!(expand_variables("%(process_num)é", expand_lookup).is_err());
+        assert!(expand_variables("%(process_num)02é", expand_lookup).is_err());
+    }
+
+    #[test]
+    fn validation_types() {
+        assert!(expand_variables("%(process_num)02d", validation_lookup).is_ok());
+        assert!(
+            expand_variables("%(program_name)s %(here)s %(ENV_HOME)s", validation_lookup).is_ok()
+        );
+        assert!(expand_variables("%(program_name)d", validation_lookup).is_err());
+        assert!(expand_variables("%(here)d", validation_lookup).is_err());
+    }
+
+    #[test]
+    fn glob_match_ok() {
+        assert!(glob_match("*.py", "main.py"));
+        assert!(glob_match("web*", "web"));
+        assert!(glob_match("web*", "webserver"));
+        assert!(glob_match("w?b", "web"));
+        assert!(glob_match("*a*b", "xxaybab"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("*.py", "main.rs"));
+        assert!(!glob_match("web?", "web"));
+        assert!(!glob_match("api", "api2"));
+    }
+
+    fn fatal_event() -> ProcessEvent {
+        ProcessEvent {
+            job_name: "web".into(),
+            process_index: 1,
+            from_state: ProcessStates::Backoff,
+            state: ProcessStates::Fatal,
+            pid: None,
+            at: std::time::UNIX_EPOCH + std::time::Duration::from_secs(42),
+        }
+    }
+
+    #[test]
+    fn event_to_json() {
+        assert_eq!(
+            fatal_event().to_json(),
+            "{\"job\":\"web\",\"process\":1,\"from_state\":\"BACKOFF\",\"state\":\"FATAL\",\"pid\":null,\"timestamp\":42}"
+        );
+    }
+
+    #[test]
+    fn post_json_to_local_listener() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: String = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request: Vec<u8> = Vec::new();
+            let mut buffer: [u8; 1024] = [0; 1024];
+            while !String::from_utf8_lossy(&request).ends_with('}') {
+                let n: usize = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        post_json(&url, &fatal_event().to_json())?;
+        let request: String = server.join().unwrap();
+        assert!(request.starts_with("POST /hook HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Type: application/json\r\n"));
+        assert!(request.ends_with(&fatal_event().to_json()));
+        Ok(())
+    }
+
+    #[test]
+    fn post_json_error_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: String = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer: [u8; 1024] = [0; 1024];
+            let _ = stream.read(&mut buffer).unwrap();
+            stream.write_all(b"HTTP/1.1 500 Oops\r\n\r\n").unwrap();
+        });
+        assert!(matches!(post_json(&url, "{}"), Err(Error::NotifyFail(_))));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn slow_url_doesnt_block_routine() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url: String = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer: [u8; 1024] = [0; 1024];
+            let _ = stream.read(&mut buffer).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+        });
+        let mut notifier: Notifier = Notifier::default();
+        notifier.rules.insert(
+            "hook".into(),
+            NotifyRule {
+                jobs: vec!["*".into()],
+                states: vec![ProcessStates::Fatal],
+                target: NotifyTarget::Url(url),
+                retries: 3,
+            },
+        );
+        notifier.notify(&fatal_event());
+        let started_at: std::time::Instant = std::time::Instant::now();
+        notifier.routine();
+        assert!(started_at.elapsed() < std::time::Duration::from_millis(200));
+        assert_eq!(notifier.queue.len(), 1);
+        while !notifier.queue.is_empty() && started_at.elapsed().as_secs() < 5 {
+            notifier.routine();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        // a failed attempt would still be queued for a retry
+        assert!(notifier.queue.is_empty());
+        server.join().unwrap();
+    }
+
+    fn state_change(state: ProcessStates) -> StateChange {
+        StateChange {
+            from_state: ProcessStates::Running,
+            state,
+            pid: Some(42),
+            tries: 1,
+            exit_code: Some(2),
+            at: std::time::SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn subscriptions() {
+        let events: Vec<String> = vec!["PROCESS_STATE_FATAL".into()];
+        assert!(is_subscribed(&events, "PROCESS_STATE_FATAL"));
+        assert!(!is_subscribed(&events, "PROCESS_STATE_EXITED"));
+        let events: Vec<String> = vec!["PROCESS_STATE".into()];
+        assert!(is_subscribed(&events, "PROCESS_STATE_EXITED"));
+    }
+
+    #[test]
+    fn process_state_payloads() {
+        assert_eq!(
+            process_state_event("web", "web", &state_change(ProcessStates::Exited), &[0, 2]),
+            (
+                "PROCESS_STATE_EXITED".into(),
+                "processname:web groupname:web from_state:RUNNING expected:1 pid:42".into()
+            )
+        );
+        assert_eq!(
+            process_state_event("web_1", "web", &state_change(ProcessStates::Fatal), &[0]).1,
+            "processname:web_1 groupname:web from_state:RUNNING"
+        );
+        let event = ListenerEvent {
+            serial: 3,
+            pool_serial: 1,
+            name: "PROCESS_STATE_FATAL".into(),
+            payload: "processname:web groupname:web from_state:BACKOFF".into(),
+        };
+        assert_eq!(
+            event.to_message("listener"),
+            "ver:3.0 server:taskmaster serial:3 pool:listener poolserial:1 \
+             eventname:PROCESS_STATE_FATAL len:48\n\
+             processname:web groupname:web from_state:BACKOFF"
+        );
+    }
+
+    #[test]
+    fn capability_names() {
+        assert_eq!(capability_number("CAP_NET_BIND_SERVICE"), Some(10));
+        assert_eq!(capability_number("net_raw"), Some(13));
+        assert_eq!(capability_number("cap_checkpoint_restore"), Some(40));
+        assert_eq!(capability_number("cap_fly"), None);
+    }
+
+    #[test]
+    fn effective_capabilities_of_current_process() {
+        assert!(effective_capabilities(std::process::id()).is_some());
+    }
+
+    #[test]
+    fn notify_messages() {
+        assert_eq!(
+            parse_notify_message("READY=1\nSTATUS=Listening on :8000\n"),
+            vec![
+                ("READY".into(), "1".into()),
+                ("STATUS".into(), "Listening on :8000".into())
+            ]
+        );
+        assert_eq!(parse_notify_message("garbage"), vec![]);
+    }
+
+    #[test]
+    fn socket_addresses() {
+        assert_eq!(
+            parse_socket_address("tcp://127.0.0.1:8000"),
+            Some(SocketAddress::Tcp("127.0.0.1:8000".into()))
+        );
+        assert_eq!(
+            parse_socket_address("unix:///run/web.sock"),
+            Some(SocketAddress::Unix("/run/web.sock".into()))
+        );
+        assert_eq!(parse_socket_address("tcp://127.0.0.1"), None);
+        assert_eq!(parse_socket_address("tcp://:8000"), None);
+        assert_eq!(parse_socket_address("unix://web.sock"), None);
+        assert_eq!(parse_socket_address("udp://127.0.0.1:53"), None);
+    }
+
+    #[test]
+    fn pending_connection() -> Result<()> {
+        let socket: ListenSocket = ListenSocket::bind(&SocketAddress::Tcp("127.0.0.1:0".into()))?;
+        assert!(!socket.has_pending_connection());
+        let ListenSocket::Tcp(ref listener) = socket else {
+            unreachable!();
+        };
+        let _client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        assert!(socket.has_pending_connection());
+        Ok(())
+    }
 }
@@ -22,12 +22,17 @@ pub enum Error {
     },
     FieldCommandIsNotSet,
     CantParseEnvEntry(String),
+    CantExpandVariable(String),
     CantOpenLogFile(String),
     CommandIsNotSupported(String),
     IO(String),
     StartJobFail(String),
     StopJobFail(String),
     StatusJobFail(String),
+    StdinJobFail(String),
+    AttachJobFail(String),
+    HookFail(String),
+    NotifyFail(String),
     ParseClientInput(String),
 }
 
@@ -0,0 +1,254 @@
+// Supervisor event listener protocol:
+// http://supervisord.org/events.html#event-listener-notification-protocol
+
+use crate::job::{ProcessStates, StateChange};
+use crate::logger::log;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::AsRawFd;
+use std::process::{ChildStdin, ChildStdout};
+
+const F_GETFL: i32 = 3;
+const F_SETFL: i32 = 4;
+const O_NONBLOCK: i32 = 0o4000;
+
+extern "C" {
+    fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+}
+
+pub const EVENT_TYPES: [&str; 9] = [
+    "PROCESS_STATE",
+    "PROCESS_STATE_STOPPED",
+    "PROCESS_STATE_STOPPING",
+    "PROCESS_STATE_STARTING",
+    "PROCESS_STATE_RUNNING",
+    "PROCESS_STATE_EXITED",
+    "PROCESS_STATE_FATAL",
+    "PROCESS_STATE_BACKOFF",
+    "EVENT",
+];
+
+#[derive(Debug, Clone)]
+pub struct ListenerEvent {
+    pub serial: u64,
+    pub pool_serial: u64,
+    pub name: String,
+    pub payload: String,
+}
+
+impl ListenerEvent {
+    pub fn to_message(&self, pool: &str) -> String {
+        format!(
+            "ver:3.0 server:taskmaster serial:{} pool:{pool} poolserial:{} eventname:{} len:{}\n{}",
+            self.serial,
+            self.pool_serial,
+            self.name,
+            self.payload.len(),
+            self.payload
+        )
+    }
+}
+
+// `PROCESS_STATE` matches every `PROCESS_STATE_*` event, `EVENT` matches all
+pub fn is_subscribed(events: &[String], event_name: &str) -> bool {
+    events.iter().any(|event| {
+        event == "EVENT" || event == event_name || event_name.starts_with(&format!("{event}_"))
+    })
+}
+
+// Name and payload of the `PROCESS_STATE_*` event of a transition
+pub fn process_state_event(
+    process_name: &str,
+    group_name: &str,
+    change: &StateChange,
+    exit_codes: &[i32],
+) -> (String, String) {
+    let mut payload: String = format!(
+        "processname:{process_name} groupname:{group_name} from_state:{}",
+        change.from_state.as_str()
+    );
+    match change.state {
+        ProcessStates::Starting | ProcessStates::Backoff => {
+            payload.push_str(&format!(" tries:{}", change.tries));
+        }
+        ProcessStates::Exited => {
+            let expected: bool = change
+                .exit_code
+                .is_some_and(|code| exit_codes.contains(&code));
+            payload.push_str(&format!(" expected:{}", expected as u8));
+        }
+        _ => {}
+    }
+    if matches!(
+        change.state,
+        ProcessStates::Running
+            | ProcessStates::Stopping
+            | ProcessStates::Exited
+            | ProcessStates::Stopped
+    ) {
+        if let Some(pid) = change.pid {
+            payload.push_str(&format!(" pid:{pid}"));
+        }
+    }
+    (format!("PROCESS_STATE_{}", change.state.as_str()), payload)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ListenerState {
+    // waiting for READY
+    Acknowledged,
+    Ready,
+    // waiting for the RESULT of an event
+    Busy,
+}
+
+impl ListenerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ListenerState::Acknowledged => "ACKNOWLEDGED",
+            ListenerState::Ready => "READY",
+            ListenerState::Busy => "BUSY",
+        }
+    }
+}
+
+// Protocol side of a listener process: events are written to its stdin and
+// it answers on its stdout
+#[derive(Debug)]
+pub struct ListenerChannel {
+    stdout: ChildStdout,
+    buffer: Vec<u8>,
+    pub state: ListenerState,
+    current: Option<ListenerEvent>,
+    // rest of the current message, stdin is nonblocking and a message can
+    // take several writes
+    unsent: Vec<u8>,
+}
+
+impl ListenerChannel {
+    pub fn new(stdout: ChildStdout) -> ListenerChannel {
+        unsafe {
+            let fd: i32 = stdout.as_raw_fd();
+            fcntl(fd, F_SETFL, fcntl(fd, F_GETFL) | O_NONBLOCK);
+        }
+        ListenerChannel {
+            stdout,
+            buffer: Vec::new(),
+            state: ListenerState::Acknowledged,
+            current: None,
+            unsent: Vec::new(),
+        }
+    }
+
+    // Read the answers of the listener, then send it the next event of the
+    // queue if it is ready. Returns false once the listener closed its
+    // stdout.
+    pub fn pump(
+        &mut self,
+        stdin: Option<&mut ChildStdin>,
+        queue: &mut VecDeque<ListenerEvent>,
+        pool: &str,
+    ) -> bool {
+        let mut read_buffer: [u8; 1024] = [0; 1024];
+        loop {
+            match self.stdout.read(&mut read_buffer) {
+                Ok(0) => {
+                    self.requeue(queue);
+                    return false;
+                }
+                Ok(n) => self.buffer.extend_from_slice(&read_buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.requeue(queue);
+                    return false;
+                }
+            }
+        }
+        while self._handle_answer(queue, pool) {}
+        let Some(stdin) = stdin else {
+            return true;
+        };
+        if self.state == ListenerState::Ready {
+            if let Some(event) = queue.pop_front() {
+                self.unsent = event.to_message(pool).into_bytes();
+                self.state = ListenerState::Busy;
+                self.current = Some(event);
+            }
+        }
+        self._send_unsent(stdin, queue, pool);
+        true
+    }
+
+    // Put back the event that was not acknowledged by the listener
+    pub fn requeue(&mut self, queue: &mut VecDeque<ListenerEvent>) {
+        self.unsent.clear();
+        if let Some(event) = self.current.take() {
+            queue.push_front(event);
+        }
+    }
+
+    // Private
+
+    // Write as much of the current message as the pipe takes
+    fn _send_unsent(
+        &mut self,
+        stdin: &mut ChildStdin,
+        queue: &mut VecDeque<ListenerEvent>,
+        pool: &str,
+    ) {
+        while !self.unsent.is_empty() {
+            match stdin.write(&self.unsent) {
+                Ok(0) => return,
+                Ok(n) => {
+                    self.unsent.drain(..n);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    log(&format!("ERROR: {pool} can't send event: {e}"));
+                    // sent again from the start once the listener is ready
+                    self.requeue(queue);
+                    self.state = ListenerState::Ready;
+                    return;
+                }
+            }
+        }
+    }
+
+    // Consume one answer of the buffer, returns false when it is incomplete
+    fn _handle_answer(&mut self, queue: &mut VecDeque<ListenerEvent>, pool: &str) -> bool {
+        let Some(end_line) = self.buffer.iter().position(|c| *c == b'\n') else {
+            return false;
+        };
+        let line: String = String::from_utf8_lossy(&self.buffer[..end_line]).into_owned();
+        match (self.state, line.strip_prefix("RESULT ")) {
+            (ListenerState::Acknowledged, _) if line == "READY" => {
+                self.buffer.drain(..=end_line);
+                self.state = ListenerState::Ready;
+            }
+            (ListenerState::Busy, Some(len)) if len.parse::<usize>().is_ok() => {
+                let body_start: usize = end_line + 1;
+                let body_end: usize = body_start + len.parse::<usize>().unwrap();
+                if self.buffer.len() < body_end {
+                    return false;
+                }
+                let body: String =
+                    String::from_utf8_lossy(&self.buffer[body_start..body_end]).into_owned();
+                self.buffer.drain(..body_end);
+                if body != "OK" {
+                    log(&format!("ERROR: {pool} rejected an event: {body}"));
+                    self.requeue(queue);
+                }
+                self.current = None;
+                self.state = ListenerState::Acknowledged;
+            }
+            _ => {
+                log(&format!(
+                    "ERROR: {pool} unexpected output in {} state: {line}",
+                    self.state.as_str()
+                ));
+                self.buffer.drain(..=end_line);
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,98 @@
+// Expansion of supervisor-like placeholders such as `%(process_num)02d` or
+// `%(ENV_HOME)s`. `%%` is a literal percent sign.
+
+use crate::error::{Error, Result};
+
+pub const KNOWN_VARIABLES: [&str; 3] = ["process_num", "program_name", "here"];
+
+pub fn expand_variables<F>(raw: &str, lookup: F) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded: String = String::new();
+    let mut rest: &str = raw;
+    while let Some(pos) = rest.find('%') {
+        expanded.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        if let Some(after) = rest.strip_prefix('%') {
+            expanded.push('%');
+            rest = after;
+            continue;
+        }
+        let Some(after_paren) = rest.strip_prefix('(') else {
+            return Err(Error::CantExpandVariable(format!(
+                "'%' must be followed by '(' or '%' in {raw}"
+            )));
+        };
+        let Some(end_name) = after_paren.find(')') else {
+            return Err(Error::CantExpandVariable(format!(
+                "Unclosed variable in {raw}"
+            )));
+        };
+        let name: &str = &after_paren[..end_name];
+        rest = &after_paren[end_name + 1..];
+        let spec_len: usize = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+            .unwrap_or(rest.len());
+        let spec: &str = &rest[..spec_len];
+        let conversion: Option<char> = rest[spec_len..].chars().next();
+        rest = &rest[spec_len + conversion.map_or(0, char::len_utf8)..];
+        let Some(value) = lookup(name) else {
+            return Err(Error::CantExpandVariable(format!(
+                "Unknown variable {name} in {raw}"
+            )));
+        };
+        expanded.push_str(&_format_value(&value, spec, conversion, raw)?);
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+// Lookup used to check a field when the config is parsed: every known
+// variable is accepted with a value of its type, so a `d` conversion is only
+// valid for `process_num`, the environment is only read when the process starts
+pub fn validation_lookup(name: &str) -> Option<String> {
+    match name {
+        "process_num" => Some(String::from("0")),
+        _ if KNOWN_VARIABLES.contains(&name) || name.starts_with("ENV_") => {
+            Some(String::from("value"))
+        }
+        _ => None,
+    }
+}
+
+// Private
+
+fn _format_value(value: &str, spec: &str, conversion: Option<char>, raw: &str) -> Result<String> {
+    let left_align: bool = spec.starts_with('-');
+    let spec: &str = spec.trim_start_matches('-');
+    let zero_pad: bool = spec.starts_with('0');
+    let width: usize = if spec.is_empty() {
+        0
+    } else {
+        spec.parse::<usize>()
+            .map_err(|_| Error::CantExpandVariable(format!("Bad width {spec} in {raw}")))?
+    };
+    match conversion {
+        Some('s') => {}
+        Some('d') => {
+            if value.parse::<i64>().is_err() {
+                return Err(Error::CantExpandVariable(format!(
+                    "{value} is not a number in {raw}"
+                )));
+            }
+        }
+        _ => {
+            return Err(Error::CantExpandVariable(format!(
+                "Variable type must be 'd' or 's' in {raw}"
+            )))
+        }
+    }
+    Ok(if left_align {
+        format!("{value:<width$}")
+    } else if zero_pad {
+        format!("{value:0>width$}")
+    } else {
+        format!("{value:>width$}")
+    })
+}
@@ -0,0 +1,88 @@
+use crate::error::{Error, Result};
+use crate::logger::log;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::time::Instant;
+
+const SIGKILL: i32 = 9;
+
+extern "C" {
+    fn kill(pid: u32, signal: i32);
+}
+
+// Hook command started in the background and reaped by the job routine, the
+// start or the stop of a process waits for its pre_start or pre_stop hook
+#[derive(Debug)]
+pub struct RunningHook {
+    pub name: String,
+    child: Child,
+    started_at: Instant,
+    timeout_secs: u32,
+}
+
+impl RunningHook {
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    // Result of the hook once it is finished, it is killed after timeout_secs
+    pub fn poll(&mut self) -> Option<Result<()>> {
+        match self.child.try_wait() {
+            Ok(Some(status)) if status.success() => Some(Ok(())),
+            Ok(Some(status)) => Some(Err(Error::HookFail(format!("{}: {status}", self.name)))),
+            Ok(None) if self.started_at.elapsed().as_secs() >= self.timeout_secs as u64 => {
+                // a negative pid is the process group, the commands started
+                // by the shell go with it
+                unsafe {
+                    kill(-(self.child.id() as i32) as u32, SIGKILL);
+                }
+                let _ = self.child.wait();
+                Some(Err(Error::HookFail(format!(
+                    "{}: timed out after {}s",
+                    self.name, self.timeout_secs
+                ))))
+            }
+            Ok(None) => None,
+            Err(e) => Some(Err(Error::HookFail(format!("{}: {e}", self.name)))),
+        }
+    }
+
+    // Returns true once the hook is finished
+    pub fn reap(&mut self) -> bool {
+        match self.poll() {
+            Some(Ok(())) => true,
+            Some(Err(e)) => {
+                log(&format!("ERROR: {e}"));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub fn hook_command(command_line: &str, work_dir: &Option<String>) -> Command {
+    let mut command = Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg(command_line)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0);
+    if let Some(work_dir) = work_dir {
+        command.current_dir(work_dir);
+    }
+    command
+}
+
+pub fn spawn_hook(name: &str, mut command: Command, timeout_secs: u32) -> Result<RunningHook> {
+    let child: Child = command
+        .spawn()
+        .map_err(|e| Error::HookFail(format!("{name}: {e}")))?;
+    Ok(RunningHook {
+        name: name.to_string(),
+        child,
+        started_at: Instant::now(),
+        timeout_secs,
+    })
+}
@@ -1,21 +1,58 @@
 use crate::logger::log;
 use std::cmp::PartialEq;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
-use std::fs::OpenOptions;
-use std::os::unix::process::CommandExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::TcpStream;
+use std::ops::Range;
+use std::os::fd::AsRawFd;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
 use std::path::Path;
-use std::process::{Child, Command, Stdio};
-use std::time::Instant;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
+use crate::activation::{ListenSocket, SocketAddress, LISTEN_PID_WRAPPER};
+use crate::capabilities::{
+    capabilities_error, capability_number, effective_capabilities, Capabilities,
+};
 use crate::error::{Error, Result};
+use crate::eventlistener::{is_subscribed, ListenerChannel, ListenerEvent};
+use crate::expand::expand_variables;
+use crate::hook::{hook_command, spawn_hook, RunningHook};
+use crate::parse::parse_env_file;
+use crate::pty::PtySession;
+use crate::sandbox::{sandbox_error, Sandbox};
+use crate::scheduling::{scheduling_error, Scheduling};
+use crate::sdnotify::NotifySocket;
+use crate::state::process_start_time;
+use crate::watch::Watcher;
+
+const SIGKILL: i32 = 9;
+// added to the time an operation may take with start_secs, start_retries and
+// stop_wait_secs, for the hooks
+const OPERATION_TIMEOUT_MARGIN_SECS: u64 = 5;
+static OPERATION_SERIAL: AtomicU64 = AtomicU64::new(1);
+const WNOHANG: i32 = 1;
+const ECHILD: i32 = 10;
+const EINVAL: i32 = 22;
+// bits of the errno above the ones of the error, see tag_setup_error
+const SETUP_STEP_SHIFT: i32 = 16;
+const F_GETFL: i32 = 3;
+const F_SETFL: i32 = 4;
+const O_NONBLOCK: i32 = 0o4000;
+// `write` data kept for a process that doesn't read its stdin
+const STDIN_BUFFER_SIZE: usize = 65536;
 
 extern "C" {
     fn kill(pid: u32, signal: i32);
+    fn waitpid(pid: i32, status: *mut i32, options: i32) -> i32;
 }
 
 extern "C" {
     pub fn umask(mask: u32) -> u32;
+    fn fcntl(fd: i32, cmd: i32, ...) -> i32;
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -25,6 +62,14 @@ pub enum AutorestartOptions {
     UnexpectedExit,
 }
 
+// values of the ioprio_set syscall
+#[derive(Debug, PartialEq, Clone)]
+pub enum IoPriorityClass {
+    Realtime = 1,
+    BestEffort = 2,
+    Idle = 3,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum StopSignals {
     HUP = 1,
@@ -36,6 +81,48 @@ pub enum StopSignals {
     TERM = 15,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum ReadyOptions {
+    Startsecs,
+    Notify,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StdinOptions {
+    Null,
+    Pipe,
+}
+
+// Options applied before exec whose errors are explained to the user
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SetupStep {
+    Sandbox = 1,
+    Scheduling = 2,
+    Capabilities = 3,
+}
+
+// Only the errno of a failed pre_exec reaches the parent, the step that
+// failed is kept in its upper bits. Nothing is allocated, as in the child.
+pub fn tag_setup_error(step: SetupStep, e: io::Error) -> io::Error {
+    let errno: i32 = e.raw_os_error().unwrap_or(EINVAL);
+    io::Error::from_raw_os_error(((step as i32) << SETUP_STEP_SHIFT) | errno)
+}
+
+// Step and errno of an error built by tag_setup_error
+fn untag_setup_error(e: io::Error) -> (Option<SetupStep>, io::Error) {
+    let Some(raw) = e.raw_os_error() else {
+        return (None, e);
+    };
+    let step: SetupStep = match raw >> SETUP_STEP_SHIFT {
+        1 => SetupStep::Sandbox,
+        2 => SetupStep::Scheduling,
+        3 => SetupStep::Capabilities,
+        _ => return (None, e),
+    };
+    let errno: i32 = raw & ((1 << SETUP_STEP_SHIFT) - 1);
+    (Some(step), io::Error::from_raw_os_error(errno))
+}
+
 // http://supervisord.org/subprocess.html#process-states
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -49,21 +136,131 @@ pub enum ProcessStates {
     Backoff,
 }
 
+impl ProcessStates {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessStates::Stopped => "STOPPED",
+            ProcessStates::Stopping => "STOPPING",
+            ProcessStates::Starting => "STARTING",
+            ProcessStates::Running => "RUNNING",
+            ProcessStates::Exited => "EXITED",
+            ProcessStates::Fatal => "FATAL",
+            ProcessStates::Backoff => "BACKOFF",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ProcessStates> {
+        [
+            ProcessStates::Stopped,
+            ProcessStates::Stopping,
+            ProcessStates::Starting,
+            ProcessStates::Running,
+            ProcessStates::Exited,
+            ProcessStates::Fatal,
+            ProcessStates::Backoff,
+        ]
+        .into_iter()
+        .find(|state| state.as_str().eq_ignore_ascii_case(name))
+    }
+}
+
+// Commands run on a process in the background, they are finished once the
+// process reached the state they asked for
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OperationKind {
+    Start,
+    Stop,
+    // stop, wait for STOPPED, then start
+    Restart,
+}
+
+impl OperationKind {
+    pub fn success_message(&self, job_name: &String) -> String {
+        match self {
+            OperationKind::Start => format!("{job_name} is started successfully!"),
+            OperationKind::Stop => format!("{job_name} is stopped successfully!"),
+            OperationKind::Restart => format!("{job_name} is restarted successfully!"),
+        }
+    }
+
+    fn error(&self, message: String) -> Error {
+        match self {
+            OperationKind::Stop => Error::StopJobFail(message),
+            OperationKind::Start | OperationKind::Restart => Error::StartJobFail(message),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Operation {
+    id: u64,
+    kind: OperationKind,
+    // operations wait for the ones queued before them on the same process
+    begun_at: Option<Instant>,
+    timeout: Duration,
+}
+
+// Transition not yet consumed by the notifications
+#[derive(Debug, Clone)]
+pub struct StateChange {
+    pub from_state: ProcessStates,
+    pub state: ProcessStates,
+    pub pid: Option<u32>,
+    pub tries: u32,
+    pub exit_code: Option<i32>,
+    pub at: SystemTime,
+}
+
 #[derive(Debug)]
 pub struct ProcessInfo {
     pub child: Option<Child>,
+    // pid of a process started by a previous taskmaster instance
+    pub adopted_pid: Option<u32>,
+    pub start_time: Option<u64>,
+    pub exit_status: Option<ExitStatus>,
+    pub pty: Option<PtySession>,
     pub state_changed_at: Instant,
     pub state: ProcessStates,
     pub nb_retries: u32,
+    // start again once STOPPED
+    pub restart_pending: bool,
+    pub operations: VecDeque<Operation>,
+    pub events: Vec<StateChange>,
+    pub listener: Option<ListenerChannel>,
+    // NOTIFY_SOCKET of a ready=notify or watchdog process
+    pub notify_socket: Option<NotifySocket>,
+    pub ready: bool,
+    // last STATUS= message
+    pub status_text: Option<String>,
+    // last WATCHDOG=1 message, or when the process became RUNNING
+    pub last_heartbeat: Instant,
+    // `write` data that the process hasn't read yet
+    pub stdin_buffer: Vec<u8>,
+    // pre_start or pre_stop hook that the start or the stop waits for
+    pub pre_hook: Option<RunningHook>,
 }
 
 impl Default for ProcessInfo {
     fn default() -> Self {
         ProcessInfo {
             child: None,
+            adopted_pid: None,
+            start_time: None,
+            exit_status: None,
+            pty: None,
             state_changed_at: Instant::now(),
             state: ProcessStates::Stopped,
             nb_retries: 0,
+            restart_pending: false,
+            operations: VecDeque::new(),
+            events: Vec::new(),
+            listener: None,
+            notify_socket: None,
+            ready: false,
+            status_text: None,
+            last_heartbeat: Instant::now(),
+            stdin_buffer: Vec::new(),
+            pre_hook: None,
         }
     }
 }
@@ -72,20 +269,153 @@ impl Clone for ProcessInfo {
     fn clone(&self) -> Self {
         ProcessInfo {
             child: None,
+            adopted_pid: None,
+            start_time: None,
+            exit_status: None,
+            pty: None,
             state_changed_at: self.state_changed_at,
             state: self.state,
             nb_retries: 0,
+            restart_pending: false,
+            operations: VecDeque::new(),
+            events: Vec::new(),
+            listener: None,
+            notify_socket: None,
+            ready: false,
+            status_text: None,
+            last_heartbeat: Instant::now(),
+            stdin_buffer: Vec::new(),
+            pre_hook: None,
         }
     }
 }
 
 impl ProcessInfo {
     fn set_state(self: &mut Self, state: ProcessStates) {
+        if state != self.state {
+            self.events.push(StateChange {
+                from_state: self.state,
+                state,
+                pid: self.pid(),
+                tries: self.nb_retries,
+                exit_code: self.exit_status.and_then(|status| status.code()),
+                at: SystemTime::now(),
+            });
+        }
+        if state == ProcessStates::Running {
+            self.last_heartbeat = Instant::now();
+        }
         self.state = state;
         self.state_changed_at = Instant::now();
     }
 
+    pub fn pid(&self) -> Option<u32> {
+        match &self.child {
+            Some(child) => Some(child.id()),
+            None => self.adopted_pid,
+        }
+    }
+
+    pub fn adopt(&mut self, pid: u32, start_time: u64, state: ProcessStates) {
+        self.child = None;
+        self.adopted_pid = Some(pid);
+        self.start_time = Some(start_time);
+        self.exit_status = None;
+        self.set_state(state);
+    }
+
+    fn set_child(&mut self, child: Child) {
+        // a child that doesn't read its stdin must not block the daemon
+        if let Some(stdin) = &child.stdin {
+            unsafe {
+                let fd: i32 = stdin.as_raw_fd();
+                fcntl(fd, F_SETFL, fcntl(fd, F_GETFL) | O_NONBLOCK);
+            }
+        }
+        self.start_time = process_start_time(child.id());
+        self.child = Some(child);
+        self.adopted_pid = None;
+        self.exit_status = None;
+        self.ready = false;
+        self.status_text = None;
+        self.stdin_buffer.clear();
+    }
+
+    // Write as much of the stdin buffer as the pipe accepts
+    fn flush_stdin(&mut self) -> io::Result<()> {
+        let Some(stdin) = self.child.as_mut().and_then(|c| c.stdin.as_mut()) else {
+            self.stdin_buffer.clear();
+            return Ok(());
+        };
+        while !self.stdin_buffer.is_empty() {
+            match stdin.write(&self.stdin_buffer) {
+                Ok(n) => {
+                    self.stdin_buffer.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.stdin_buffer.clear();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        self.child = None;
+        self.adopted_pid = None;
+        self.start_time = None;
+        self.notify_socket = None;
+    }
+
+    // SIGKILL the process and wait for it when it is our child
+    fn kill_now(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        } else if let Some(pid) = self.adopted_pid {
+            unsafe {
+                kill(pid, SIGKILL);
+            }
+        }
+    }
+
+    // Same as Child::try_wait, but also works for adopted processes. An
+    // adopted process is not our child anymore, so its exit status can't be
+    // collected: it is reported as killed.
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        if self.exit_status.is_some() {
+            return Ok(self.exit_status);
+        }
+        if let Some(child) = &mut self.child {
+            self.exit_status = child.try_wait()?;
+            return Ok(self.exit_status);
+        }
+        let Some(pid) = self.adopted_pid else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no process"));
+        };
+        let mut status: i32 = 0;
+        let ret: i32 = unsafe { waitpid(pid as i32, &mut status, WNOHANG) };
+        if ret == pid as i32 {
+            self.exit_status = Some(ExitStatus::from_raw(status));
+        } else if ret == -1 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() != Some(ECHILD) {
+                return Err(error);
+            }
+            if process_start_time(pid) != self.start_time {
+                self.exit_status = Some(ExitStatus::from_raw(SIGKILL));
+            }
+        }
+        Ok(self.exit_status)
+    }
+
     fn can_start(self: &Self) -> bool {
+        if self.pre_hook.is_some() {
+            return false;
+        }
         match self.state {
             ProcessStates::Stopped => true,
             ProcessStates::Fatal => true,
@@ -104,23 +434,92 @@ impl ProcessInfo {
     }
 }
 
+// Processes of a `restart --rolling`, restarted batch by batch
+#[derive(Debug)]
+pub struct RollingRestart {
+    // operation answered once the rolling restart is done or aborted
+    id: u64,
+    pending: VecDeque<usize>,
+    batch_size: usize,
+    // batch that must be RUNNING before the next one is restarted
+    current: Vec<usize>,
+}
+
 pub struct Job {
     pub command: String,
     pub arguments: Option<Vec<String>>,
+    // run `command` through `/bin/sh -c`
+    pub shell: bool,
     pub num_procs: u32,
     pub auto_start: bool,
     pub auto_restart: AutorestartOptions,
     pub exit_codes: Vec<i32>,
     pub start_secs: u32,
+    // RUNNING after start_secs, or once the process sent READY=1 within start_secs
+    pub ready: ReadyOptions,
+    // restart a RUNNING process that sent no WATCHDOG=1 nor touched heartbeat_file for this long, 0 to disable
+    pub watchdog_secs: u32,
+    pub heartbeat_file: Option<String>,
     pub start_retries: u32,
     pub stop_signal: StopSignals,
     pub stop_wait_secs: u32,
     pub stderr_file: Option<String>,
     pub stdout_file: Option<String>,
+    pub stdin: StdinOptions,
+    // spawn the processes on a pseudo-terminal
+    pub pty: bool,
     pub environment: Option<HashMap<String, String>>,
+    // start from an empty environment, only keeping environment_allowlist
+    pub clean_environment: bool,
+    pub environment_allowlist: Option<Vec<String>>,
+    pub environment_file: Option<String>,
     pub work_dir: Option<String>,
     pub umask: Option<u32>,
+    // paths watched with inotify, processes are restarted when they change
+    pub watch: Option<Vec<String>>,
+    pub watch_debounce_ms: u32,
+    // hook commands run through /bin/sh -c around the state transitions
+    pub pre_start: Option<String>,
+    pub post_start: Option<String>,
+    pub pre_stop: Option<String>,
+    pub post_stop: Option<String>,
+    pub hook_timeout_secs: u32,
+    // event types sent to the processes of an [eventlistener:x] section
+    pub events: Option<Vec<String>>,
+    pub buffer_size: u32,
+    // namespaces the processes are started in
+    pub private_network: bool,
+    pub private_tmp: bool,
+    pub private_pid: bool,
+    pub read_only_paths: Option<Vec<String>>,
+    pub no_new_privs: bool,
+    // capabilities kept by the processes, every capability if None
+    pub capabilities: Option<Vec<String>>,
+    pub ambient_capabilities: Option<Vec<String>>,
+    pub nice: Option<i32>,
+    pub cpu_affinity: Option<Vec<usize>>,
+    pub ioprio_class: Option<IoPriorityClass>,
+    pub ioprio_level: Option<u32>,
+    pub oom_score_adj: Option<i32>,
+    // pid file written by a forking command, the pid in it is tracked once the command exits
+    pub pidfile: Option<String>,
+    // listening socket shared by the processes, passed as fd 3
+    pub socket: Option<SocketAddress>,
+    // start the job on the first connection to the socket
+    pub socket_lazy: bool,
+    // directory of the config file, for %(here)s
+    pub config_dir: Option<String>,
     pub processes: Vec<ProcessInfo>,
+    pub watcher: Option<Result<Watcher>>,
+    pub watch_changed_at: Option<Instant>,
+    pub running_hooks: Vec<RunningHook>,
+    // events waiting for a READY listener
+    pub event_queue: VecDeque<ListenerEvent>,
+    pub pool_serial: u64,
+    pub listen_socket: Option<Result<ListenSocket>>,
+    pub rolling_restart: Option<RollingRestart>,
+    // results of the operations not yet sent to the clients
+    pub finished_operations: Vec<(u64, Result<String>)>,
 }
 
 impl fmt::Debug for Job {
@@ -130,35 +529,103 @@ impl fmt::Debug for Job {
             "Job [\n\
                 \tcommand: {},\n\
                 \targuments: {:?},\n\
+                \tshell: {},\n\
                 \tnum_procs: {},\n\
                 \tauto_start: {},\n\
                 \tauto_restart: {:?},\n\
                 \texit_codes: {:?},\n\
                 \tstart_secs: {},\n\
+                \tready: {:?},\n\
+                \twatchdog_secs: {:?},\n\
+                \theartbeat_file: {:?},\n\
                 \tstart_retries: {},\n\
                 \tstop_signal: {:?},\n\
                 \tstop_wait_secs: {},\n\
                 \tstderr_file: {:?},\n\
                 \tstdout_file: {:?},\n\
+                \tstdin: {:?},\n\
+                \tpty: {},\n\
                 \tenvironment: {:?},\n\
+                \tclean_environment: {},\n\
+                \tenvironment_allowlist: {:?},\n\
+                \tenvironment_file: {:?},\n\
                 \twork_dir: {:?},\n\
-                \tumask: {:?}\n\
+                \tumask: {:?},\n\
+                \twatch: {:?},\n\
+                \twatch_debounce_ms: {},\n\
+                \tpre_start: {:?},\n\
+                \tpost_start: {:?},\n\
+                \tpre_stop: {:?},\n\
+                \tpost_stop: {:?},\n\
+                \thook_timeout_secs: {},\n\
+                \tevents: {:?},\n\
+                \tbuffer_size: {},\n\
+                \tprivate_network: {},\n\
+                \tprivate_tmp: {},\n\
+                \tprivate_pid: {},\n\
+                \tread_only_paths: {:?},\n\
+                \tno_new_privs: {},\n\
+                \tcapabilities: {:?},\n\
+                \tambient_capabilities: {:?},\n\
+                \tnice: {:?},\n\
+                \tcpu_affinity: {:?},\n\
+                \tioprio_class: {:?},\n\
+                \tioprio_level: {:?},\n\
+                \toom_score_adj: {:?},\n\
+                \tpidfile: {:?},\n\
+                \tsocket: {:?},\n\
+                \tsocket_lazy: {},\n\
+                \tconfig_dir: {:?}\n\
             ]",
             self.command,
             self.arguments,
+            self.shell,
             self.num_procs,
             self.auto_start,
             self.auto_restart,
             self.exit_codes,
             self.start_secs,
+            self.ready,
+            self.watchdog_secs,
+            self.heartbeat_file,
             self.start_retries,
             self.stop_signal,
             self.stop_wait_secs,
             self.stderr_file,
             self.stdout_file,
+            self.stdin,
+            self.pty,
             self.environment,
+            self.clean_environment,
+            self.environment_allowlist,
+            self.environment_file,
             self.work_dir,
-            self.umask
+            self.umask,
+            self.watch,
+            self.watch_debounce_ms,
+            self.pre_start,
+            self.post_start,
+            self.pre_stop,
+            self.post_stop,
+            self.hook_timeout_secs,
+            self.events,
+            self.buffer_size,
+            self.private_network,
+            self.private_tmp,
+            self.private_pid,
+            self.read_only_paths,
+            self.no_new_privs,
+            self.capabilities,
+            self.ambient_capabilities,
+            self.nice,
+            self.cpu_affinity,
+            self.ioprio_class,
+            self.ioprio_level,
+            self.oom_score_adj,
+            self.pidfile,
+            self.socket,
+            self.socket_lazy,
+            self.config_dir
         )
     }
 }
@@ -168,20 +635,62 @@ impl Default for Job {
         Job {
             command: String::new(),
             arguments: None,
+            shell: false,
             num_procs: 1,
             auto_start: false,
             auto_restart: AutorestartOptions::UnexpectedExit,
             exit_codes: vec![0],
             start_secs: 1,
+            ready: ReadyOptions::Startsecs,
+            watchdog_secs: 0,
+            heartbeat_file: None,
             start_retries: 3,
             stop_signal: StopSignals::TERM,
             stop_wait_secs: 10,
             stderr_file: None,
             stdout_file: None,
+            stdin: StdinOptions::Null,
+            pty: false,
             environment: None,
+            clean_environment: false,
+            environment_allowlist: None,
+            environment_file: None,
             work_dir: None,
             umask: None,
+            watch: None,
+            watch_debounce_ms: 500,
+            pre_start: None,
+            post_start: None,
+            pre_stop: None,
+            post_stop: None,
+            hook_timeout_secs: 10,
+            events: None,
+            buffer_size: 10,
+            private_network: false,
+            private_tmp: false,
+            private_pid: false,
+            read_only_paths: None,
+            no_new_privs: false,
+            capabilities: None,
+            ambient_capabilities: None,
+            nice: None,
+            cpu_affinity: None,
+            ioprio_class: None,
+            ioprio_level: None,
+            oom_score_adj: None,
+            pidfile: None,
+            socket: None,
+            socket_lazy: false,
+            config_dir: None,
             processes: vec![ProcessInfo::default()],
+            watcher: None,
+            watch_changed_at: None,
+            running_hooks: Vec::new(),
+            event_queue: VecDeque::new(),
+            pool_serial: 0,
+            listen_socket: None,
+            rolling_restart: None,
+            finished_operations: Vec::new(),
         }
     }
 }
@@ -191,20 +700,62 @@ impl Clone for Job {
         Job {
             command: self.command.clone(),
             arguments: self.arguments.clone(),
+            shell: self.shell,
             num_procs: self.num_procs,
             auto_start: self.auto_start,
             auto_restart: self.auto_restart.clone(),
             exit_codes: self.exit_codes.clone(),
             start_secs: self.start_secs,
+            ready: self.ready.clone(),
+            watchdog_secs: self.watchdog_secs,
+            heartbeat_file: self.heartbeat_file.clone(),
             start_retries: self.start_retries,
             stop_signal: self.stop_signal.clone(),
             stop_wait_secs: self.stop_wait_secs,
             stderr_file: self.stderr_file.clone(),
             stdout_file: self.stdout_file.clone(),
+            stdin: self.stdin.clone(),
+            pty: self.pty,
             environment: self.environment.clone(),
+            clean_environment: self.clean_environment,
+            environment_allowlist: self.environment_allowlist.clone(),
+            environment_file: self.environment_file.clone(),
             work_dir: self.work_dir.clone(),
             umask: self.umask,
+            watch: self.watch.clone(),
+            watch_debounce_ms: self.watch_debounce_ms,
+            pre_start: self.pre_start.clone(),
+            post_start: self.post_start.clone(),
+            pre_stop: self.pre_stop.clone(),
+            post_stop: self.post_stop.clone(),
+            hook_timeout_secs: self.hook_timeout_secs,
+            events: self.events.clone(),
+            buffer_size: self.buffer_size,
+            private_network: self.private_network,
+            private_tmp: self.private_tmp,
+            private_pid: self.private_pid,
+            read_only_paths: self.read_only_paths.clone(),
+            no_new_privs: self.no_new_privs,
+            capabilities: self.capabilities.clone(),
+            ambient_capabilities: self.ambient_capabilities.clone(),
+            nice: self.nice,
+            cpu_affinity: self.cpu_affinity.clone(),
+            ioprio_class: self.ioprio_class.clone(),
+            ioprio_level: self.ioprio_level,
+            oom_score_adj: self.oom_score_adj,
+            pidfile: self.pidfile.clone(),
+            socket: self.socket.clone(),
+            socket_lazy: self.socket_lazy,
+            config_dir: self.config_dir.clone(),
             processes: vec![ProcessInfo::default(); self.num_procs as usize],
+            watcher: None,
+            watch_changed_at: None,
+            running_hooks: Vec::new(),
+            event_queue: VecDeque::new(),
+            pool_serial: 0,
+            listen_socket: None,
+            rolling_restart: None,
+            finished_operations: Vec::new(),
         }
     }
 }
@@ -213,19 +764,53 @@ impl PartialEq for Job {
     fn eq(&self, other: &Self) -> bool {
         self.command == other.command
             && self.arguments == other.arguments
+            && self.shell == other.shell
             && self.num_procs == other.num_procs
             && self.auto_start == other.auto_start
             && self.auto_restart == other.auto_restart
             && self.exit_codes == other.exit_codes
             && self.start_secs == other.start_secs
+            && self.ready == other.ready
+            && self.watchdog_secs == other.watchdog_secs
+            && self.heartbeat_file == other.heartbeat_file
             && self.start_retries == other.start_retries
             && self.stop_signal == other.stop_signal
             && self.stop_wait_secs == other.stop_wait_secs
             && self.stderr_file == other.stderr_file
             && self.stdout_file == other.stdout_file
+            && self.stdin == other.stdin
+            && self.pty == other.pty
             && self.environment == other.environment
+            && self.clean_environment == other.clean_environment
+            && self.environment_allowlist == other.environment_allowlist
+            && self.environment_file == other.environment_file
             && self.work_dir == other.work_dir
             && self.umask == other.umask
+            && self.watch == other.watch
+            && self.watch_debounce_ms == other.watch_debounce_ms
+            && self.pre_start == other.pre_start
+            && self.post_start == other.post_start
+            && self.pre_stop == other.pre_stop
+            && self.post_stop == other.post_stop
+            && self.hook_timeout_secs == other.hook_timeout_secs
+            && self.events == other.events
+            && self.buffer_size == other.buffer_size
+            && self.private_network == other.private_network
+            && self.private_tmp == other.private_tmp
+            && self.private_pid == other.private_pid
+            && self.read_only_paths == other.read_only_paths
+            && self.no_new_privs == other.no_new_privs
+            && self.capabilities == other.capabilities
+            && self.ambient_capabilities == other.ambient_capabilities
+            && self.nice == other.nice
+            && self.cpu_affinity == other.cpu_affinity
+            && self.ioprio_class == other.ioprio_class
+            && self.ioprio_level == other.ioprio_level
+            && self.oom_score_adj == other.oom_score_adj
+            && self.pidfile == other.pidfile
+            && self.socket == other.socket
+            && self.socket_lazy == other.socket_lazy
+            && self.config_dir == other.config_dir
     }
 }
 
@@ -235,105 +820,135 @@ impl Job {
         job_name: &String,
         target_process: Option<usize>,
     ) -> Result<String> {
-        let mut start_index: usize = 0;
-        let mut end_index: usize = self.num_procs as usize;
-        if let Some(nb) = target_process {
-            if nb < self.num_procs as usize {
-                start_index = nb;
-                end_index = nb + 1;
-            } else {
-                let error_message = Error::StartJobFail(format!(
-                    "Target index must be inferior to {}",
-                    self.num_procs
-                ));
-                log(&error_message.to_string());
-                return Err(error_message);
+        let targets: Range<usize> = self._target_range(target_process, Error::StartJobFail)?;
+        let mut hook_error: Option<Error> = None;
+        if let Some(ref address) = self.socket {
+            if !matches!(self.listen_socket, Some(Ok(_))) {
+                self.listen_socket = Some(ListenSocket::bind(address));
+            }
+            if let Some(Err(e)) = &self.listen_socket {
+                return Err(Error::StartJobFail(format!("{job_name}: {e}")));
             }
         }
 
-        for i in start_index..end_index {
+        for i in targets {
             if self.processes[i].can_start() == false {
                 log(&format!(
                     "INFO: {job_name}:{i} is in a state where it can't start"
                 ));
                 continue;
             }
-            let mut command = Command::new(&self.command);
-            if let Some(args) = &self.arguments {
-                command.args(args);
-            }
-
-            if let Some(environment) = &self.environment {
-                command.envs(environment);
-            }
-
-            if let Some(config_umask) = self.umask {
-                unsafe {
-                    command.pre_exec(move || {
-                        umask(config_umask);
-                        Ok(())
-                    });
-                }
-            }
-
-            if let Some(ref work_dir) = self.work_dir {
-                let path = Path::new(work_dir);
-                if path.is_dir() == true {
-                    command.current_dir(work_dir);
-                } else {
-                    return Err(Error::StartJobFail(format!(
-                        "{work_dir} is not a directory!"
-                    )));
-                }
-            }
-
-            if let Some(ref stderr_file) = self.stderr_file {
-                match OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .open(stderr_file)
-                {
-                    Ok(file) => {
-                        command.stderr(Stdio::from(file));
-                    }
-                    Err(e) => return Err(Error::StartJobFail(e.to_string())),
-                }
+            let Some(pre_start) = self.pre_start.clone() else {
+                self._spawn_process(job_name, i)?;
+                continue;
+            };
+            // the process is spawned by the routine once the hook succeeds
+            if let Err(e) = self._spawn_pre_hook("pre_start", &pre_start, job_name, i) {
+                log(&format!("ERROR: {e}"));
+                self._backoff_after_failed_start(i, job_name);
+                hook_error = Some(e);
             }
+        }
+        if let Some(e) = hook_error {
+            return Err(e);
+        }
+        Ok(format!("{job_name} is started successfully!"))
+    }
 
-            if let Some(ref stdout_file) = self.stdout_file {
-                match OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .open(stdout_file)
-                {
-                    Ok(file) => {
-                        command.stdout(Stdio::from(file));
-                    }
-                    Err(e) => return Err(Error::StartJobFail(e.to_string())),
-                }
-            }
+    // Queue an operation on the processes, returns the ids to wait for with
+    // operation_result
+    pub fn queue_operation(
+        &mut self,
+        job_name: &String,
+        target_process: Option<usize>,
+        kind: OperationKind,
+    ) -> Result<Vec<u64>> {
+        let to_error: fn(String) -> Error = match kind {
+            OperationKind::Stop => Error::StopJobFail,
+            OperationKind::Start | OperationKind::Restart => Error::StartJobFail,
+        };
+        let targets: Range<usize> = self._target_range(target_process, to_error)?;
+        let pre_start_timeout: u64 = match self.pre_start {
+            Some(_) => self.hook_timeout_secs as u64,
+            None => 0,
+        };
+        let start_timeout: u64 = (self.start_secs as u64 + pre_start_timeout)
+            * (self.start_retries as u64 + 1)
+            + (1..=self.start_retries as u64).sum::<u64>();
+        let stop_timeout: u64 = self.stop_wait_secs as u64
+            + match self.pre_stop {
+                Some(_) => self.hook_timeout_secs as u64,
+                None => 0,
+            };
+        let timeout_secs: u64 = OPERATION_TIMEOUT_MARGIN_SECS
+            + match kind {
+                OperationKind::Start => start_timeout,
+                OperationKind::Stop => stop_timeout,
+                OperationKind::Restart => start_timeout + stop_timeout,
+            };
+        let mut ids: Vec<u64> = Vec::new();
+        for i in targets {
+            let id: u64 = OPERATION_SERIAL.fetch_add(1, Ordering::Relaxed);
+            self.processes[i].operations.push_back(Operation {
+                id,
+                kind,
+                begun_at: None,
+                timeout: Duration::from_secs(timeout_secs),
+            });
+            ids.push(id);
+        }
+        self._handle_operations(job_name);
+        Ok(ids)
+    }
 
-            match command.spawn() {
-                Ok(child_process) => {
-                    self.processes[i as usize].nb_retries += 1;
-                    self.processes[i as usize].child = Some(child_process);
-                    self.processes[i as usize].set_state(ProcessStates::Starting);
-                    log(&format!("INFO: {job_name}:{i} is now in STARTING state"));
-                }
-                Err(e) => return Err(Error::StartJobFail(e.to_string())),
-            }
+    // Result of a queued operation, None while it is not finished. An
+    // operation that is nowhere to be found was dropped with its process.
+    pub fn operation_result(&mut self, id: u64) -> Option<Result<String>> {
+        if let Some(position) = self.finished_operations.iter().position(|(i, _)| *i == id) {
+            return Some(self.finished_operations.remove(position).1);
+        }
+        let is_queued: bool = self.processes.iter().any(|process| {
+            process
+                .operations
+                .iter()
+                .any(|operation| operation.id == id)
+        }) || self
+            .rolling_restart
+            .as_ref()
+            .is_some_and(|rolling_restart| rolling_restart.id == id);
+        match is_queued {
+            true => None,
+            false => Some(Err(Error::Default("operation was cancelled".into()))),
         }
-        Ok(format!("{job_name} is started successfully!"))
     }
 
-    pub fn restart(
-        self: &mut Self,
+    // Restart batch_size processes at a time, the next batch is restarted once
+    // the current one is RUNNING. Returns the id of the operation, see
+    // `operation_result`.
+    pub fn rolling_restart(
+        &mut self,
         job_name: &String,
         target_process: Option<usize>,
-    ) -> Result<String> {
-        self.stop(job_name, target_process)?;
-        self.start(job_name, target_process)?;
-        Ok(format!("{job_name} is restarted successfully!"))
+        batch_size: u32,
+    ) -> Result<u64> {
+        let targets: Range<usize> = self._target_range(target_process, Error::StartJobFail)?;
+        if self.rolling_restart.is_some() {
+            return Err(Error::StartJobFail(format!(
+                "{job_name} is already being restarted"
+            )));
+        }
+        let id: u64 = OPERATION_SERIAL.fetch_add(1, Ordering::Relaxed);
+        self.rolling_restart = Some(RollingRestart {
+            id,
+            pending: targets.collect(),
+            batch_size: batch_size as usize,
+            current: Vec::new(),
+        });
+        if let Err(e) = self._restart_next_batch(job_name) {
+            self.rolling_restart = None;
+            return Err(e);
+        }
+        Ok(id)
     }
 
     pub fn stop(
@@ -341,35 +956,43 @@ impl Job {
         job_name: &String,
         target_process: Option<usize>,
     ) -> Result<String> {
-        let mut start_index: usize = 0;
-        let mut end_index: usize = self.num_procs as usize;
-        if let Some(nb) = target_process {
-            if nb < self.num_procs as usize {
-                start_index = nb;
-                end_index = nb + 1;
-            } else {
-                let error_message = Error::StopJobFail(format!(
-                    "Target index must be inferior to {}",
-                    self.num_procs
-                ));
-                log(&error_message.to_string());
-                return Err(error_message);
-            }
-        }
-        for i in start_index..end_index {
+        let targets: Range<usize> = self._target_range(target_process, Error::StopJobFail)?;
+        for i in targets {
             let process: &mut ProcessInfo = &mut self.processes[i as usize];
+            process.restart_pending = false;
+            if process.state == ProcessStates::Backoff {
+                // nothing is running, the next try is cancelled
+                process.nb_retries = 0;
+                process.release();
+                process.set_state(ProcessStates::Stopped);
+                log(&format!("INFO: {job_name}:{i} is now in STOPPED state"));
+                continue;
+            }
             if process.can_stop() == false {
+                if process.state != ProcessStates::Stopping {
+                    if let Some(pre_start) = process.pre_hook.take() {
+                        // left to finish in the background
+                        self.running_hooks.push(pre_start);
+                        log(&format!("INFO: {job_name}:{i} start is cancelled"));
+                        continue;
+                    }
+                }
                 log(&format!(
                     "INFO: {job_name}:{i} is in a state where it can't stop"
                 ));
                 continue;
             }
-            let child_id: u32 = process.child.as_ref().unwrap().id();
-            unsafe {
-                kill(child_id, self.stop_signal.to_owned() as i32);
-            }
-            self.processes[i].set_state(ProcessStates::Stopping);
+            process.set_state(ProcessStates::Stopping);
             log(&format!("INFO: {job_name}:{i} is now in STOPPING state"));
+            let Some(pre_stop) = self.pre_stop.clone() else {
+                self._send_stop_signal(i);
+                continue;
+            };
+            // the signal is sent by the routine once the hook is finished
+            if let Err(e) = self._spawn_pre_hook("pre_stop", &pre_stop, job_name, i) {
+                log(&format!("ERROR: {e}"));
+                self._send_stop_signal(i);
+            }
         }
         Ok(format!("{job_name} is stopped successfully!"))
     }
@@ -379,45 +1002,292 @@ impl Job {
         job_name: &String,
         target_process: Option<usize>,
     ) -> Result<String> {
-        let mut start_index: usize = 0;
-        let mut end_index: usize = self.num_procs as usize;
-        if let Some(nb) = target_process {
-            if nb < self.num_procs as usize {
-                start_index = nb;
-                end_index = nb + 1;
-            } else {
-                let error_message = Error::StatusJobFail(format!(
-                    "Target index must be inferior to {}",
-                    self.num_procs
-                ));
-                log(&error_message.to_string());
-                return Err(error_message);
-            }
-        }
+        let targets: Range<usize> = self._target_range(target_process, Error::StatusJobFail)?;
         let mut return_message: String = format!("\nProgram: {job_name:^3}");
-        for i in start_index..end_index {
+        for i in targets {
             let process: &mut ProcessInfo = &mut self.processes[i as usize];
             return_message = format!(
                 "{return_message}\n--> nb: {i:^3} | state: {:^3?}",
                 process.state
             );
+            let has_capabilities: bool = self.capabilities.is_some()
+                || self.ambient_capabilities.is_some()
+                || self.no_new_privs;
+            if let (true, Some(pid)) = (has_capabilities, process.pid()) {
+                if let Some(caps) = effective_capabilities(pid) {
+                    return_message = format!("{return_message} | CapEff: {caps}");
+                }
+            }
+            if let Some(listener) = &process.listener {
+                return_message =
+                    format!("{return_message} | listener: {}", listener.state.as_str());
+            }
+            if let Some(status_text) = &process.status_text {
+                return_message = format!("{return_message} | status: {status_text}");
+            }
         }
         return_message = format!("{return_message}\n",);
         Ok(return_message)
     }
-    pub fn stop_job_now(self: &mut Self) {
-        for p in self.processes.iter_mut() {
-            if let Some(c) = &mut p.child {
-                let _ = c.kill();
-                let _ = c.wait();
+    pub fn write_stdin(
+        &mut self,
+        job_name: &String,
+        target_process: Option<usize>,
+        data: &String,
+    ) -> Result<String> {
+        let targets: Range<usize> = self._target_range(target_process, Error::StdinJobFail)?;
+        // a process that can't take the data doesn't keep it from the others
+        let mut errors: Vec<String> = Vec::new();
+        for i in targets {
+            let process: &mut ProcessInfo = &mut self.processes[i];
+            if process
+                .child
+                .as_ref()
+                .and_then(|c| c.stdin.as_ref())
+                .is_none()
+            {
+                errors.push(format!("{job_name}:{i} has no stdin pipe"));
+                continue;
+            }
+            if process.stdin_buffer.len() + data.len() > STDIN_BUFFER_SIZE {
+                errors.push(format!(
+                    "{job_name}:{i} doesn't read its stdin, {} bytes are still waiting",
+                    process.stdin_buffer.len()
+                ));
+                continue;
+            }
+            process.stdin_buffer.extend_from_slice(data.as_bytes());
+            if let Err(e) = process.flush_stdin() {
+                errors.push(format!("{job_name}:{i} {e}"));
+                continue;
+            }
+            if !process.stdin_buffer.is_empty() {
+                errors.push(format!(
+                    "{job_name}:{i} has read {} of {} bytes, the rest is written once it reads its stdin",
+                    data.len().saturating_sub(process.stdin_buffer.len()),
+                    data.len()
+                ));
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(format!("{job_name} stdin is written successfully!")),
+            false => Err(Error::StdinJobFail(errors.join("\n"))),
+        }
+    }
+
+    pub fn close_stdin(
+        &mut self,
+        job_name: &String,
+        target_process: Option<usize>,
+    ) -> Result<String> {
+        let targets: Range<usize> = self._target_range(target_process, Error::StdinJobFail)?;
+        for i in targets {
+            let process: &mut ProcessInfo = &mut self.processes[i];
+            if !process.stdin_buffer.is_empty() {
+                log(&format!(
+                    "INFO: {job_name}:{i} {} bytes written to stdin are dropped",
+                    process.stdin_buffer.len()
+                ));
+                process.stdin_buffer.clear();
+            }
+            if let Some(child) = process.child.as_mut() {
+                // dropping the pipe sends EOF to the process
+                child.stdin = None;
+                log(&format!("INFO: {job_name}:{i} stdin is closed"));
+            }
+        }
+        Ok(format!("{job_name} stdin is closed successfully!"))
+    }
+
+    pub fn attach(
+        &mut self,
+        job_name: &String,
+        target_process: Option<usize>,
+        stream: TcpStream,
+    ) -> Result<String> {
+        let index: usize = match target_process {
+            Some(nb) if nb < self.num_procs as usize => nb,
+            None if self.num_procs == 1 => 0,
+            _ => {
+                return Err(Error::AttachJobFail(format!(
+                    "Target a single process of {job_name}: {job_name}:n"
+                )))
+            }
+        };
+        let Some(pty) = self.processes[index].pty.as_mut() else {
+            return Err(Error::AttachJobFail(format!(
+                "{job_name}:{index} has no pseudo-terminal"
+            )));
+        };
+        pty.attach(stream)?;
+        log(&format!("INFO: client attached to {job_name}:{index}"));
+        Ok(String::new())
+    }
+
+    // Queue an event for the processes of an event listener subscribed to it
+    pub fn push_event(&mut self, pool: &String, serial: u64, name: &str, payload: &str) {
+        let Some(ref events) = self.events else {
+            return;
+        };
+        if !is_subscribed(events, name) {
+            return;
+        }
+        if self.event_queue.len() >= self.buffer_size as usize {
+            self.event_queue.pop_front();
+            log(&format!(
+                "ERROR: {pool} event buffer overflowed, discarding the oldest event"
+            ));
+        }
+        self.pool_serial += 1;
+        self.event_queue.push_back(ListenerEvent {
+            serial,
+            pool_serial: self.pool_serial,
+            name: name.to_owned(),
+            payload: payload.to_owned(),
+        });
+    }
+
+    // Track a process started by a previous taskmaster instance
+    pub fn adopt_process(
+        &mut self,
+        job_name: &String,
+        process_index: usize,
+        pid: u32,
+        start_time: u64,
+        state: ProcessStates,
+    ) {
+        let uses_notify_socket: bool = self._uses_notify_socket();
+        let process: &mut ProcessInfo = &mut self.processes[process_index];
+        process.adopt(pid, start_time, state);
+        // NOTIFY_SOCKET of the process is still the same path
+        if uses_notify_socket {
+            match NotifySocket::bind(job_name, process_index) {
+                Ok(socket) => process.notify_socket = Some(socket),
+                Err(e) => log(&format!("ERROR: {job_name}:{process_index} {e}")),
+            }
+        }
+    }
+
+    // Pids of the processes and of the hooks running in the background
+    pub fn pids(&self) -> Vec<u32> {
+        self.processes
+            .iter()
+            .filter_map(ProcessInfo::pid)
+            .chain(
+                self.processes
+                    .iter()
+                    .flat_map(|p| p.pre_hook.as_ref().map(RunningHook::pid)),
+            )
+            .chain(self.running_hooks.iter().map(RunningHook::pid))
+            .collect()
+    }
+
+    // Change the number of instances without touching the ones that are kept.
+    // Removed instances are stopped and dropped once they are STOPPED, new
+    // ones are started if the job is autostarted or already running.
+    pub fn scale(&mut self, job_name: &String, num_procs: u32) -> Result<String> {
+        let old_num_procs: usize = self.num_procs as usize;
+        let new_num_procs: usize = num_procs as usize;
+        let is_active: bool =
+            self.auto_start || self.processes[..old_num_procs].iter().any(|p| p.can_stop());
+        for i in new_num_procs..old_num_procs {
+            self.stop(job_name, Some(i))?;
+        }
+        if self.processes.len() < new_num_procs {
+            self.processes.resize(new_num_procs, ProcessInfo::default());
+        }
+        self.num_procs = num_procs;
+        log(&format!(
+            "INFO: {job_name} is scaled from {old_num_procs} to {new_num_procs} processes"
+        ));
+        if is_active {
+            for i in old_num_procs..new_num_procs {
+                // still stopping after an earlier scale down
+                match self.processes[i].state {
+                    ProcessStates::Stopping => self.processes[i].restart_pending = true,
+                    _ => {
+                        self.start(job_name, Some(i))?;
+                    }
+                }
             }
         }
+        self._drop_retired_processes();
+        Ok(format!("{job_name} is scaled to {num_procs} processes!"))
+    }
+
+    // Same as `==`, without looking at `num_procs`
+    pub fn eq_except_num_procs(&self, other: &Job) -> bool {
+        let mut other: Job = other.clone();
+        other.num_procs = self.num_procs;
+        *self == other
+    }
+
+    // Stop the job before a reload removes or replaces it: its processes go
+    // through STOPPING like the ones removed by a scale down, pre_stop hooks
+    // included, and the job can be dropped once `is_retired`
+    pub fn retire(&mut self, job_name: &String) {
+        let _ = self.stop(job_name, None);
+        self.num_procs = 0;
+        self.rolling_restart = None;
+        // the replacement binds them again
+        self.socket = None;
+        self.socket_lazy = false;
+        self.listen_socket = None;
+        self.watch = None;
+        self.watcher = None;
+        self._drop_retired_processes();
+    }
+
+    // No process left and every hook is reaped
+    pub fn is_retired(&self) -> bool {
+        self.processes.is_empty() && self.running_hooks.is_empty()
     }
 
     // from http://supervisord.org/subprocess.html#process-states
     pub fn processes_routine(self: &mut Self, job_name: &String) {
-        let nb_processes: usize = self.num_procs as usize;
+        let nb_processes: usize = self.processes.len();
         for process_index in 0..nb_processes {
+            let process: &mut ProcessInfo = &mut self.processes[process_index];
+            if let Some(pty) = process.pty.as_mut() {
+                if !pty.pump() {
+                    process.pty = None;
+                }
+            }
+            if let Some(listener) = process.listener.as_mut() {
+                // events are only sent to RUNNING listeners
+                let stdin = match process.state {
+                    ProcessStates::Running => process.child.as_mut().and_then(|c| c.stdin.as_mut()),
+                    _ => None,
+                };
+                if !listener.pump(stdin, &mut self.event_queue, job_name) {
+                    process.listener = None;
+                }
+            }
+            if !process.stdin_buffer.is_empty() {
+                if let Err(e) = process.flush_stdin() {
+                    log(&format!(
+                        "ERROR: {job_name}:{process_index} can't write to stdin: {e}"
+                    ));
+                }
+            }
+            if let Some(notify_socket) = process.notify_socket.as_ref() {
+                for (key, value) in notify_socket.receive() {
+                    match key.as_str() {
+                        "READY" if value == "1" => process.ready = true,
+                        "STATUS" => process.status_text = Some(value),
+                        "WATCHDOG" if value == "1" => process.last_heartbeat = Instant::now(),
+                        _ => {}
+                    }
+                }
+            }
+            self._handle_pre_hook(process_index, job_name);
+            if process_index >= self.num_procs as usize {
+                // removed by a scale down, only wait for it to stop
+                if self.processes[process_index].state == ProcessStates::Stopping {
+                    self._handle_stopping(process_index, job_name);
+                }
+                continue;
+            }
             match self.processes[process_index].state {
                 ProcessStates::Starting => self._handle_starting(process_index, job_name),
                 ProcessStates::Backoff => self._handle_backoff(process_index, job_name),
@@ -428,35 +1298,733 @@ impl Job {
                 _ => continue,
             };
         }
+        self._drop_retired_processes();
+        self._handle_watch(job_name);
+        self._handle_socket_activation(job_name);
+        self._handle_rolling_restart(job_name);
+        self._handle_operations(job_name);
+        self.running_hooks.retain_mut(|hook| !hook.reap());
     }
 
     // Private
 
+    fn _target_range(
+        &self,
+        target_process: Option<usize>,
+        to_error: fn(String) -> Error,
+    ) -> Result<Range<usize>> {
+        match target_process {
+            None => Ok(0..self.num_procs as usize),
+            Some(nb) if nb < self.num_procs as usize => Ok(nb..nb + 1),
+            Some(_) => {
+                let error_message = to_error(format!(
+                    "Target index must be inferior to {}",
+                    self.num_procs
+                ));
+                log(&error_message.to_string());
+                Err(error_message)
+            }
+        }
+    }
+
+    fn _expand(&self, raw: &str, job_name: &str, process_index: usize) -> Result<String> {
+        expand_variables(raw, |name| match name {
+            "process_num" => Some(process_index.to_string()),
+            "program_name" => Some(job_name.to_owned()),
+            "here" => Some(self.config_dir.clone().unwrap_or(String::from("."))),
+            _ => std::env::var(name.strip_prefix("ENV_")?).ok(),
+        })
+    }
+
+    fn _build_command(&self, job_name: &str, process_index: usize) -> Result<Command> {
+        let expand = |raw: &String| self._expand(raw, job_name, process_index);
+        let mut command = if self.shell {
+            let mut command = Command::new("/bin/sh");
+            command.arg("-c").arg(expand(&self.command)?);
+            command
+        } else {
+            Command::new(expand(&self.command)?)
+        };
+        if let Some(args) = &self.arguments {
+            for arg in args {
+                command.arg(expand(arg)?);
+            }
+        }
+
+        if let Some(Ok(_)) = self.listen_socket {
+            let mut wrapper = Command::new("/bin/sh");
+            wrapper
+                .arg("-c")
+                .arg(LISTEN_PID_WRAPPER)
+                .arg(command.get_program())
+                .args(command.get_args());
+            command = wrapper;
+        }
+
+        if self.clean_environment {
+            command.env_clear();
+            for key in self.environment_allowlist.iter().flatten() {
+                if let Ok(value) = std::env::var(key) {
+                    command.env(key, value);
+                }
+            }
+        }
+
+        if let Some(ref environment_file) = self.environment_file {
+            let environment_file: String = expand(environment_file)?;
+            let file_environment: HashMap<String, String> = parse_env_file(&environment_file)
+                .map_err(|e| Error::StartJobFail(format!("{environment_file}: {e}")))?;
+            command.envs(file_environment);
+        }
+
+        // inline values override the ones of environment_file
+        if let Some(environment) = &self.environment {
+            for (key, value) in environment {
+                command.env(key, expand(value)?);
+            }
+        }
+
+        if self.watchdog_secs > 0 {
+            command.env(
+                "WATCHDOG_USEC",
+                (self.watchdog_secs as u64 * 1_000_000).to_string(),
+            );
+        }
+
+        // once the environment is built, env_clear would remove LISTEN_FDS
+        if let Some(Ok(ref listen_socket)) = self.listen_socket {
+            listen_socket.apply(&mut command);
+        }
+
+        if let Some(config_umask) = self.umask {
+            unsafe {
+                command.pre_exec(move || {
+                    umask(config_umask);
+                    Ok(())
+                });
+            }
+        }
+
+        let sandbox: Sandbox = Sandbox {
+            private_network: self.private_network,
+            private_tmp: self.private_tmp,
+            private_pid: self.private_pid,
+            read_only_paths: self
+                .read_only_paths
+                .iter()
+                .flatten()
+                .map(expand)
+                .collect::<Result<Vec<String>>>()?,
+        };
+        if sandbox.is_enabled() {
+            sandbox.apply(&mut command)?;
+        }
+
+        let scheduling: Scheduling = Scheduling {
+            nice: self.nice,
+            cpu_affinity: self.cpu_affinity.clone(),
+            io_priority: match (&self.ioprio_class, self.ioprio_level) {
+                (None, None) => None,
+                (class, level) => Some((
+                    class.clone().unwrap_or(IoPriorityClass::BestEffort),
+                    level.unwrap_or(4),
+                )),
+            },
+            oom_score_adj: self.oom_score_adj,
+        };
+        if scheduling.is_enabled() {
+            scheduling.apply(&mut command)?;
+        }
+
+        // after the namespaces and the scheduling, which need privileges
+        let to_numbers =
+            |names: &Vec<String>| names.iter().filter_map(|n| capability_number(n)).collect();
+        let capabilities: Capabilities = Capabilities {
+            keep: self.capabilities.as_ref().map(to_numbers),
+            ambient: self
+                .ambient_capabilities
+                .as_ref()
+                .map(to_numbers)
+                .unwrap_or_default(),
+            no_new_privs: self.no_new_privs,
+        };
+        if capabilities.is_enabled() {
+            capabilities.apply(&mut command);
+        }
+
+        if let Some(ref work_dir) = self.work_dir {
+            let work_dir: String = expand(work_dir)?;
+            let path = Path::new(&work_dir);
+            if path.is_dir() {
+                command.current_dir(work_dir);
+            } else {
+                return Err(Error::StartJobFail(format!(
+                    "{work_dir} is not a directory!"
+                )));
+            }
+        }
+
+        if self.pty {
+            // stdin, stdout and stderr are the pseudo-terminal
+            return Ok(command);
+        }
+
+        match self.stdin {
+            StdinOptions::Null => command.stdin(Stdio::null()),
+            StdinOptions::Pipe => command.stdin(Stdio::piped()),
+        };
+
+        if let Some(ref stderr_file) = self.stderr_file {
+            match OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(expand(stderr_file)?)
+            {
+                Ok(file) => {
+                    command.stderr(Stdio::from(file));
+                }
+                Err(e) => return Err(Error::StartJobFail(e.to_string())),
+            }
+        }
+
+        if let Some(ref stdout_file) = self.stdout_file {
+            match OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(expand(stdout_file)?)
+            {
+                Ok(file) => {
+                    command.stdout(Stdio::from(file));
+                }
+                Err(e) => return Err(Error::StartJobFail(e.to_string())),
+            }
+        }
+
+        // stdout of an event listener is the protocol channel
+        if self.events.is_some() {
+            command.stdout(Stdio::piped());
+        }
+        Ok(command)
+    }
+
+    fn _uses_notify_socket(&self) -> bool {
+        self.ready == ReadyOptions::Notify || self.watchdog_secs > 0
+    }
+
+    fn _spawn_error(&self, job_name: &String, e: io::Error) -> Error {
+        match untag_setup_error(e) {
+            (Some(SetupStep::Sandbox), e) => sandbox_error(job_name, e),
+            (Some(SetupStep::Scheduling), e) => scheduling_error(job_name, e),
+            (Some(SetupStep::Capabilities), e) => capabilities_error(job_name, e),
+            (None, e) => Error::StartJobFail(e.to_string()),
+        }
+    }
+
+    fn _handle_watch(&mut self, job_name: &String) {
+        let Some(ref paths) = self.watch else {
+            return;
+        };
+        if self.watcher.is_none() {
+            let watcher: Result<Watcher> = paths
+                .iter()
+                .map(|path| self._expand(path, job_name, 0))
+                .collect::<Result<Vec<String>>>()
+                .and_then(|paths| Watcher::new(&paths));
+            if let Err(ref e) = watcher {
+                log(&format!("ERROR: {job_name} can't watch files: {e}"));
+            }
+            self.watcher = Some(watcher);
+        }
+        let Some(Ok(watcher)) = self.watcher.as_mut() else {
+            return;
+        };
+        if watcher.poll() {
+            self.watch_changed_at = Some(Instant::now());
+        }
+        // wait for the changes to settle before restarting
+        if let Some(changed_at) = self.watch_changed_at {
+            if changed_at.elapsed() >= Duration::from_millis(self.watch_debounce_ms as u64) {
+                self.watch_changed_at = None;
+                log(&format!("INFO: {job_name} watched files changed"));
+                self._restart_active_processes(job_name);
+            }
+        }
+    }
+
+    // A lazy job is started by the first connection to its socket, once none
+    // of its processes is running
+    fn _handle_socket_activation(&mut self, job_name: &String) {
+        let (true, Some(address)) = (self.socket_lazy, &self.socket) else {
+            return;
+        };
+        if self.listen_socket.is_none() {
+            let listen_socket: Result<ListenSocket> = ListenSocket::bind(address);
+            if let Err(ref e) = listen_socket {
+                log(&format!("ERROR: {job_name} can't listen: {e}"));
+            }
+            self.listen_socket = Some(listen_socket);
+        }
+        let Some(Ok(listen_socket)) = &self.listen_socket else {
+            return;
+        };
+        let is_idle: bool = self.processes[..self.num_procs as usize]
+            .iter()
+            .all(|p| matches!(p.state, ProcessStates::Stopped | ProcessStates::Exited));
+        if is_idle && listen_socket.has_pending_connection() {
+            log(&format!("INFO: {job_name} is started by a connection"));
+            let _ = self.start(job_name, None);
+        }
+    }
+
+    // Restart the processes that are not STOPPED or FATAL
+    fn _restart_active_processes(&mut self, job_name: &String) {
+        for i in 0..self.num_procs as usize {
+            match self.processes[i].state {
+                ProcessStates::Stopped | ProcessStates::Fatal => continue,
+                _ => {
+                    let _ = self._restart_process(job_name, i);
+                }
+            }
+        }
+    }
+
+    // Start the process once it is STOPPED
+    fn _restart_process(&mut self, job_name: &String, process_index: usize) -> Result<()> {
+        match self.processes[process_index].state {
+            ProcessStates::Running | ProcessStates::Starting => {
+                self.stop(job_name, Some(process_index))?;
+                self.processes[process_index].restart_pending = true;
+            }
+            ProcessStates::Stopping => self.processes[process_index].restart_pending = true,
+            _ => {
+                self.start(job_name, Some(process_index))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn _restart_next_batch(&mut self, job_name: &String) -> Result<()> {
+        let Some(rolling_restart) = self.rolling_restart.as_mut() else {
+            return Ok(());
+        };
+        let batch_size: usize = rolling_restart
+            .batch_size
+            .min(rolling_restart.pending.len());
+        let batch: Vec<usize> = rolling_restart.pending.drain(..batch_size).collect();
+        rolling_restart.current = batch.clone();
+        log(&format!("INFO: {job_name} rolling restart of {batch:?}"));
+        for process_index in batch {
+            self._restart_process(job_name, process_index)?;
+        }
+        Ok(())
+    }
+
+    // Move to the next batch once the current one is RUNNING. A process of the
+    // batch that didn't come back up aborts the rolling restart, so the
+    // other processes keep serving.
+    fn _handle_rolling_restart(&mut self, job_name: &String) {
+        let Some(rolling_restart) = self.rolling_restart.as_ref() else {
+            return;
+        };
+        let mut is_batch_running: bool = true;
+        for process_index in rolling_restart.current.iter() {
+            let (state, waits_for_pre_start) = match self.processes.get(*process_index) {
+                Some(process) if *process_index < self.num_procs as usize => {
+                    (process.state, process.pre_hook.is_some())
+                }
+                // removed by a scale down meanwhile
+                _ => continue,
+            };
+            match state {
+                ProcessStates::Running => {}
+                // stays STOPPED until its pre_start hook is done
+                ProcessStates::Stopped if waits_for_pre_start => is_batch_running = false,
+                ProcessStates::Starting | ProcessStates::Stopping | ProcessStates::Backoff => {
+                    is_batch_running = false
+                }
+                ProcessStates::Stopped | ProcessStates::Exited | ProcessStates::Fatal => {
+                    let message: String = format!(
+                        "{job_name}:{process_index} of the batch {:?} is {}, rolling restart is aborted",
+                        rolling_restart.current,
+                        state.as_str()
+                    );
+                    log(&format!("ERROR: {message}"));
+                    self._finish_rolling_restart(Err(Error::StartJobFail(message)));
+                    return;
+                }
+            }
+        }
+        if !is_batch_running {
+            return;
+        }
+        if rolling_restart.pending.is_empty() {
+            log(&format!("INFO: {job_name} rolling restart is done"));
+            self._finish_rolling_restart(Ok(OperationKind::Restart.success_message(job_name)));
+            return;
+        }
+        if let Err(e) = self._restart_next_batch(job_name) {
+            log(&format!(
+                "ERROR: {job_name} rolling restart is aborted: {e}"
+            ));
+            self._finish_rolling_restart(Err(e));
+        }
+    }
+
+    fn _finish_rolling_restart(&mut self, result: Result<String>) {
+        if let Some(rolling_restart) = self.rolling_restart.take() {
+            self.finished_operations.push((rolling_restart.id, result));
+        }
+    }
+
+    // Spawn the command of the process, after its pre_start hook
+    fn _spawn_process(&mut self, job_name: &String, i: usize) -> Result<()> {
+        let mut command: Command = self._build_command(job_name, i)?;
+        // the socket of the previous run unlinks its path when dropped, it
+        // must go before the new one is bound to the same path
+        self.processes[i].notify_socket = None;
+        let notify_socket: Option<NotifySocket> = match self._uses_notify_socket() {
+            true => {
+                let socket: NotifySocket = NotifySocket::bind(job_name, i)?;
+                command.env("NOTIFY_SOCKET", &socket.path);
+                Some(socket)
+            }
+            false => None,
+        };
+        let pty: Option<PtySession> = if self.pty {
+            let log_file: Option<File> = match self.stdout_file {
+                Some(ref stdout_file) => Some(
+                    OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(false)
+                        .open(self._expand(stdout_file, job_name, i)?)
+                        .map_err(|e| Error::StartJobFail(e.to_string()))?,
+                ),
+                None => None,
+            };
+            Some(PtySession::spawn_on_pty(&mut command, log_file)?)
+        } else {
+            None
+        };
+        match command.spawn() {
+            Ok(mut child_process) => {
+                let listener: Option<ListenerChannel> = match self.events {
+                    Some(_) => child_process.stdout.take().map(ListenerChannel::new),
+                    None => None,
+                };
+                let process: &mut ProcessInfo = &mut self.processes[i];
+                process.nb_retries += 1;
+                process.set_child(child_process);
+                process.pty = pty;
+                process.listener = listener;
+                process.notify_socket = notify_socket;
+                process.set_state(ProcessStates::Starting);
+                log(&format!("INFO: {job_name}:{i} is now in STARTING state"));
+            }
+            Err(e) => return Err(self._spawn_error(job_name, e)),
+        }
+        Ok(())
+    }
+
+    fn _hook_command(
+        &self,
+        command_line: &String,
+        job_name: &String,
+        process_index: usize,
+    ) -> Result<Command> {
+        let expand = |raw: &String| self._expand(raw, job_name, process_index);
+        let work_dir: Option<String> = self.work_dir.as_ref().map(expand).transpose()?;
+        let mut command: Command = hook_command(&expand(command_line)?, &work_dir);
+        command
+            .env("TASKMASTER_JOB", job_name)
+            .env("TASKMASTER_PROCESS_NUM", process_index.to_string());
+        if let Some(pid) = self.processes[process_index].pid() {
+            command.env("TASKMASTER_PID", pid.to_string());
+        }
+        Ok(command)
+    }
+
+    fn _spawn_pre_hook(
+        &mut self,
+        hook_name: &str,
+        command_line: &String,
+        job_name: &String,
+        process_index: usize,
+    ) -> Result<()> {
+        let running_hook: RunningHook = spawn_hook(
+            &format!("{job_name}:{process_index} {hook_name}"),
+            self._hook_command(command_line, job_name, process_index)?,
+            self.hook_timeout_secs,
+        )?;
+        self.processes[process_index].pre_hook = Some(running_hook);
+        Ok(())
+    }
+
+    // Go on with the start or the stop of the process once its pre_start or
+    // pre_stop hook is finished
+    fn _handle_pre_hook(&mut self, process_index: usize, job_name: &String) {
+        let process: &mut ProcessInfo = &mut self.processes[process_index];
+        let Some(result) = process.pre_hook.as_mut().and_then(RunningHook::poll) else {
+            return;
+        };
+        process.pre_hook = None;
+        match (process.state, result) {
+            (ProcessStates::Stopping, result) => {
+                if let Err(e) = result {
+                    log(&format!("ERROR: {e}"));
+                }
+                self._send_stop_signal(process_index);
+            }
+            // removed by a scale down meanwhile
+            _ if process_index >= self.num_procs as usize => {}
+            (_, Ok(())) => {
+                if let Err(e) = self._spawn_process(job_name, process_index) {
+                    log(&format!("ERROR: {e}"));
+                    self._backoff_after_failed_start(process_index, job_name);
+                }
+            }
+            (_, Err(e)) => {
+                log(&format!("ERROR: {e}"));
+                self._backoff_after_failed_start(process_index, job_name);
+            }
+        }
+    }
+
+    fn _backoff_after_failed_start(&mut self, process_index: usize, job_name: &String) {
+        let process: &mut ProcessInfo = &mut self.processes[process_index];
+        process.nb_retries += 1;
+        process.set_state(ProcessStates::Backoff);
+        log(&format!(
+            "INFO: {job_name}:{process_index} is now in BACKOFF state"
+        ));
+    }
+
+    // The stop_wait_secs delay starts once the signal is sent
+    fn _send_stop_signal(&mut self, process_index: usize) {
+        let process: &mut ProcessInfo = &mut self.processes[process_index];
+        if let Some(pid) = process.pid() {
+            unsafe {
+                kill(pid, self.stop_signal.to_owned() as i32);
+            }
+        }
+        process.set_state(ProcessStates::Stopping);
+    }
+
+    fn _spawn_hook(
+        &mut self,
+        hook_name: &str,
+        hook: Option<String>,
+        job_name: &String,
+        process_index: usize,
+    ) {
+        let Some(command_line) = hook else {
+            return;
+        };
+        let running_hook: Result<RunningHook> = self
+            ._hook_command(&command_line, job_name, process_index)
+            .and_then(|command| {
+                spawn_hook(
+                    &format!("{job_name}:{process_index} {hook_name}"),
+                    command,
+                    self.hook_timeout_secs,
+                )
+            });
+        match running_hook {
+            Ok(running_hook) => self.running_hooks.push(running_hook),
+            Err(e) => log(&format!("ERROR: {e}")),
+        }
+    }
+
+    fn _drop_retired_processes(&mut self) {
+        while self.processes.len() > self.num_procs as usize
+            && self.processes.last().unwrap().state != ProcessStates::Stopping
+        {
+            let process: ProcessInfo = self.processes.pop().unwrap();
+            self.running_hooks.extend(process.pre_hook);
+            for operation in process.operations {
+                self.finished_operations.push((
+                    operation.id,
+                    Err(operation
+                        .kind
+                        .error("process is removed by a scale down".into())),
+                ));
+            }
+        }
+    }
+
+    fn _handle_operations(&mut self, job_name: &String) {
+        for process_index in 0..self.processes.len() {
+            while let Some(result) = self._step_operation(job_name, process_index) {
+                let operation: Operation = self.processes[process_index]
+                    .operations
+                    .pop_front()
+                    .unwrap();
+                self.finished_operations.push((operation.id, result));
+            }
+        }
+    }
+
+    // Advance the first operation of the process, returns its result once it
+    // is finished
+    fn _step_operation(
+        &mut self,
+        job_name: &String,
+        process_index: usize,
+    ) -> Option<Result<String>> {
+        let name: String = format!("{job_name}:{process_index}");
+        loop {
+            let process: &mut ProcessInfo = &mut self.processes[process_index];
+            let state: ProcessStates = process.state;
+            let can_stop: bool = process.can_stop();
+            let waits_for_pre_start: bool =
+                process.pre_hook.is_some() && state != ProcessStates::Stopping;
+            let operation: &mut Operation = process.operations.front_mut()?;
+            if let Some(begun_at) = operation.begun_at {
+                if begun_at.elapsed() >= operation.timeout {
+                    return Some(Err(operation
+                        .kind
+                        .error(format!("{name} timed out in {} state", state.as_str()))));
+                }
+            }
+            match (operation.kind, operation.begun_at.is_some(), state) {
+                // the stop of a previous operation is not finished
+                (OperationKind::Start, false, ProcessStates::Stopping) => return None,
+                (OperationKind::Start, false, _) => {
+                    operation.begun_at = Some(Instant::now());
+                    if let Err(e) = self.start(job_name, Some(process_index)) {
+                        return Some(Err(e));
+                    }
+                }
+                (OperationKind::Start, true, ProcessStates::Running) => {
+                    return Some(Ok(format!("{name} is started")))
+                }
+                (OperationKind::Start, true, ProcessStates::Starting | ProcessStates::Backoff) => {
+                    return None
+                }
+                (OperationKind::Start, true, _) if waits_for_pre_start => return None,
+                (OperationKind::Start, true, state) => {
+                    return Some(Err(Error::StartJobFail(format!(
+                        "{name} is {}",
+                        state.as_str()
+                    ))))
+                }
+                (OperationKind::Stop | OperationKind::Restart, false, _) => {
+                    operation.begun_at = Some(Instant::now());
+                    if can_stop || waits_for_pre_start || state == ProcessStates::Backoff {
+                        if let Err(e) = self.stop(job_name, Some(process_index)) {
+                            return Some(Err(e));
+                        }
+                    }
+                }
+                (OperationKind::Stop | OperationKind::Restart, true, ProcessStates::Stopping) => {
+                    return None
+                }
+                (OperationKind::Stop, true, _) => return Some(Ok(format!("{name} is stopped"))),
+                // stopped, the restart goes on with a start
+                (OperationKind::Restart, true, _) => {
+                    operation.kind = OperationKind::Start;
+                    operation.begun_at = None;
+                }
+            }
+        }
+    }
+
+    // A forking command exits once its daemon is started: the pid of the
+    // pidfile is then tracked in place of the command. Returns true while the
+    // pidfile is awaited, up to start_secs.
+    fn _wait_for_pidfile(&mut self, process_index: usize, job_name: &String) -> bool {
+        let Some(ref pidfile) = self.pidfile else {
+            return false;
+        };
+        let process: &mut ProcessInfo = &mut self.processes[process_index];
+        if process.child.is_none() {
+            return false;
+        }
+        match process.try_wait() {
+            Ok(Some(status)) if status.success() => {}
+            _ => return false,
+        }
+        let pidfile: String = match self._expand(pidfile, job_name, process_index) {
+            Ok(pidfile) => pidfile,
+            Err(e) => {
+                log(&format!("ERROR: {job_name}:{process_index} {e}"));
+                return false;
+            }
+        };
+        let process: &mut ProcessInfo = &mut self.processes[process_index];
+        let daemon: Option<(u32, u64)> = fs::read_to_string(&pidfile)
+            .ok()
+            .and_then(|content| content.trim().parse::<u32>().ok())
+            .and_then(|pid| Some((pid, process_start_time(pid)?)))
+            // a daemon started before the command is a stale pidfile
+            .filter(|(_, start_time)| Some(*start_time) >= process.start_time);
+        if let Some((pid, start_time)) = daemon {
+            process.adopt(pid, start_time, process.state);
+            log(&format!(
+                "INFO: {job_name}:{process_index} follows pid {pid} of {pidfile}"
+            ));
+            return false;
+        }
+        if process.state_changed_at.elapsed().as_secs() < self.start_secs as u64 {
+            return true;
+        }
+        log(&format!(
+            "ERROR: {job_name}:{process_index} {pidfile} doesn't hold the pid of a running process"
+        ));
+        false
+    }
+
     fn _handle_starting(self: &mut Self, process_index: usize, job_name: &String) {
+        if self._wait_for_pidfile(process_index, job_name) {
+            return;
+        }
         let process: &mut ProcessInfo = &mut self.processes[process_index];
-        let child: &mut Child = if let Some(c) = &mut process.child {
-            c
-        } else {
+        if process.pid().is_none() {
             log(&format!(
                 "FATAL: {job_name}:{process_index} Unexpected error while starting"
             ));
             panic!("Why process state is STARTING but child is NONE ????");
-        };
-        match child.try_wait() {
+        }
+        match process.try_wait() {
             Ok(Some(_)) => {
                 process.set_state(ProcessStates::Backoff);
-                process.child = None;
+                process.release();
                 log(&format!(
                     "INFO: {job_name}:{process_index} is now in BACKOFF state"
                 ));
             }
             Ok(None) => {
-                if process.state_changed_at.elapsed().as_secs() >= self.start_secs as u64 {
+                let start_secs_elapsed: bool =
+                    process.state_changed_at.elapsed().as_secs() >= self.start_secs as u64;
+                let is_ready: bool = match self.ready {
+                    ReadyOptions::Startsecs => start_secs_elapsed,
+                    ReadyOptions::Notify => process.ready,
+                };
+                if !is_ready && start_secs_elapsed {
+                    log(&format!(
+                        "ERROR: {job_name}:{process_index} didn't send READY=1 within {}s",
+                        self.start_secs
+                    ));
+                    process.kill_now();
+                    process.set_state(ProcessStates::Backoff);
+                    process.release();
+                    log(&format!(
+                        "INFO: {job_name}:{process_index} is now in BACKOFF state"
+                    ));
+                } else if is_ready {
                     process.nb_retries = 0;
                     process.set_state(ProcessStates::Running);
                     log(&format!(
                         "INFO: {job_name}:{process_index} is now in RUNNING state"
                     ));
+                    self._spawn_hook(
+                        "post_start",
+                        self.post_start.clone(),
+                        job_name,
+                        process_index,
+                    );
                 }
             }
             Err(e) => {
@@ -477,7 +2045,7 @@ impl Job {
             return;
         }
         process.nb_retries = 0;
-        process.child = None;
+        process.release();
         process.set_state(ProcessStates::Fatal);
         log(&format!(
             "INFO: {job_name}:{process_index} reached retry limit"
@@ -489,25 +2057,35 @@ impl Job {
 
     fn _handle_stopping(&mut self, process_index: usize, job_name: &String) {
         let process: &mut ProcessInfo = &mut self.processes[process_index];
-        let child: &mut Child = if let Some(c) = &mut process.child {
-            c
-        } else {
+        if process.pid().is_none() {
             log(&format!(
                 "FATAL: {job_name}:{process_index} Unexpected error while stopping"
             ));
             panic!("Why process state is STOPPING but child is NONE ????");
-        };
-        match child.try_wait() {
+        }
+        match process.try_wait() {
             Ok(Some(_)) => {
                 process.set_state(ProcessStates::Stopped);
-                process.child = None;
+                process.release();
                 log(&format!(
                     "INFO: {job_name}:{process_index} is now in STOPPED state"
                 ));
+                // exited before its pre_stop hook is finished
+                self.running_hooks.extend(process.pre_hook.take());
+                let restart_pending: bool = process.restart_pending;
+                process.restart_pending = false;
+                self._spawn_hook("post_stop", self.post_stop.clone(), job_name, process_index);
+                if restart_pending {
+                    let _ = self.start(job_name, Some(process_index));
+                }
             }
             Ok(None) => {
-                if process.state_changed_at.elapsed().as_secs() >= self.stop_wait_secs as u64 {
-                    let _ = child.kill();
+                if process.pre_hook.is_none()
+                    && process.state_changed_at.elapsed().as_secs() >= self.stop_wait_secs as u64
+                {
+                    unsafe {
+                        kill(process.pid().unwrap(), SIGKILL);
+                    }
                 }
             }
             Err(e) => {
@@ -519,39 +2097,67 @@ impl Job {
     }
 
     fn _handle_running(&mut self, process_index: usize, job_name: &String) {
+        if self._wait_for_pidfile(process_index, job_name) {
+            return;
+        }
         let process: &mut ProcessInfo = &mut self.processes[process_index];
-        let child: &mut Child = if let Some(c) = &mut process.child {
-            c
-        } else {
+        if process.pid().is_none() {
             log(&format!(
                 "FATAL: {job_name}:{process_index} Unexpected error while running"
             ));
             panic!("Why process state is RUNNING but child is NONE ????");
-        };
-        match child.try_wait() {
+        }
+        match process.try_wait() {
             Ok(Some(_)) => {
                 process.set_state(ProcessStates::Exited);
                 log(&format!(
                     "INFO: {job_name}:{process_index} is now in EXITED state"
                 ));
             }
+            Ok(None) => self._check_watchdog(process_index, job_name),
             Err(e) => {
                 log(&format!(
                     "ERROR: {job_name}:{process_index} Error attempting to wait: {e}"
                 ));
             }
-            _ => return,
         }
     }
 
+    // A process that stopped sending heartbeats is hung: stop it and start it
+    // again once it is STOPPED
+    fn _check_watchdog(&mut self, process_index: usize, job_name: &String) {
+        if self.watchdog_secs == 0 {
+            return;
+        }
+        let process: &ProcessInfo = &self.processes[process_index];
+        let mut silence: Duration = process.last_heartbeat.elapsed();
+        if let Some(ref heartbeat_file) = self.heartbeat_file {
+            let file_age: Option<Duration> = self
+                ._expand(heartbeat_file, job_name, process_index)
+                .ok()
+                .and_then(|path| fs::metadata(path).ok()?.modified().ok())
+                .and_then(|modified| modified.elapsed().ok());
+            if let Some(file_age) = file_age {
+                silence = silence.min(file_age);
+            }
+        }
+        if silence.as_secs() < self.watchdog_secs as u64 {
+            return;
+        }
+        log(&format!(
+            "ERROR: {job_name}:{process_index} watchdog timeout, no heartbeat for {}s",
+            silence.as_secs()
+        ));
+        let _ = self.stop(job_name, Some(process_index));
+        self.processes[process_index].restart_pending = true;
+    }
+
     fn _handle_exited(&mut self, process_index: usize, job_name: &String) {
         let process: &mut ProcessInfo = &mut self.processes[process_index];
-        let child: &mut Child = if let Some(c) = &mut process.child {
-            c
-        } else {
+        if process.pid().is_none() {
             return;
-        };
-        match child.try_wait() {
+        }
+        match process.try_wait() {
             // terminated by signal
             Ok(Some(status)) if status.code().is_none() => {
                 if self.auto_restart == AutorestartOptions::Always
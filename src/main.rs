@@ -1,21 +1,39 @@
+mod activation;
+mod capabilities;
 mod config;
 mod error;
+mod eventlistener;
+mod expand;
+mod glob;
+mod hook;
 mod job;
 mod logger;
+mod notify;
 mod parse;
+mod pty;
+mod reaper;
+mod sandbox;
+mod scheduling;
+mod sdnotify;
+mod state;
+mod watch;
 
 use config::Config;
 use error::{Error, Result};
-use job::Job;
+use job::{Job, OperationKind};
 use logger::{log, Logger};
-use parse::parse_client_input;
+use parse::{
+    parse_client_input, parse_num_procs_from_client_input, parse_rolling_from_client_input,
+    parse_stdin_data_from_client_input, ClientInput,
+};
 use std::env::args;
 use std::io::{prelude::*, ErrorKind};
-use std::net::TcpListener;
+use std::net::{TcpListener, TcpStream};
 use std::thread::sleep;
 use std::time::Duration;
 
 const SIGHUP: i32 = 1;
+const STATE_FILE: &str = "taskmaster.state";
 static mut RELOAD_CONFIG: bool = false;
 static mut LOGGER: Logger = Logger::new();
 
@@ -32,25 +50,108 @@ extern "C" fn handle_sighup(_signum: i32) {
 fn try_reload_config(config: &mut Config, config_file: &String) {
     unsafe {
         if RELOAD_CONFIG {
-            match config.reload_config(&config_file) {
-                Err(_) => {
-                    log("ERROR: Can't reload file");
-                }
-                Ok(()) => {
-                    println!("Reloaded config: {config:#?}");
-                    log(&format!("INFO: Config file is reloaded"));
-                }
-            }
+            reload_config(config, config_file);
             RELOAD_CONFIG = false;
         }
     }
 }
 
+// A config file that can't be parsed is reported and the last applied one
+// is kept
+fn reload_config(config: &mut Config, config_file: &String) -> String {
+    match config.reload_config(config_file) {
+        Err(e) => {
+            log(&format!("ERROR: Can't reload file: {e}"));
+            format!("Can't reload {config_file}, the previous config is kept: {e}")
+        }
+        Ok(()) => {
+            println!("Reloaded config: {config:#?}");
+            log("INFO: Config file is reloaded");
+            format!("{config_file} is reloaded")
+        }
+    }
+}
+
+// Result of a target, start, stop and restart answer once their operations
+// are finished
+enum Reply {
+    Done(String),
+    Waiting {
+        job_name: String,
+        kind: OperationKind,
+        ids: Vec<u64>,
+        results: Vec<Result<String>>,
+    },
+}
+
+// Client waiting for the replies of its command
+struct PendingReply {
+    stream: TcpStream,
+    replies: Vec<Reply>,
+}
+
+// Send the replies of the commands whose targets are all finished
+fn send_finished_replies(pending_replies: &mut Vec<PendingReply>, config: &mut Config) {
+    pending_replies.retain_mut(|pending_reply| {
+        for reply in pending_reply.replies.iter_mut() {
+            let Reply::Waiting {
+                job_name,
+                kind,
+                ids,
+                results,
+            } = reply
+            else {
+                continue;
+            };
+            let Some(job) = config.get_mut(job_name) else {
+                *reply = Reply::Done(format!("{job_name} is removed"));
+                continue;
+            };
+            while let Some(result) = ids
+                .get(results.len())
+                .and_then(|id| job.operation_result(*id))
+            {
+                results.push(result);
+            }
+            if results.len() < ids.len() {
+                continue;
+            }
+            let errors: Vec<String> = results
+                .iter()
+                .filter_map(|result| result.as_ref().err().map(Error::to_string))
+                .collect();
+            *reply = match errors.is_empty() {
+                true => Reply::Done(kind.success_message(job_name)),
+                false => Reply::Done(errors.join("\n")),
+            };
+        }
+        let mut messages: Vec<String> = Vec::new();
+        for reply in pending_reply.replies.iter() {
+            match reply {
+                Reply::Done(message) => messages.push(message.clone()),
+                Reply::Waiting { .. } => return true,
+            }
+        }
+        if let Err(e) = pending_reply
+            .stream
+            .write(&join_results(&messages).into_bytes())
+        {
+            log(&format!("ERROR: Can't send reply: {e}"));
+        }
+        false
+    });
+}
+
 fn server_routine(listener: &TcpListener, config: &mut Config, config_file: &String) -> Result<()> {
     let duration = Duration::from_millis(100);
+    let mut pending_replies: Vec<PendingReply> = Vec::new();
     for stream in listener.incoming() {
         try_reload_config(config, config_file);
         config.jobs_routine();
+        send_finished_replies(&mut pending_replies, config);
+        if let Err(e) = config.save_state(STATE_FILE) {
+            log(&format!("ERROR: Can't save state file: {e}"));
+        }
         match stream {
             Ok(mut s) => {
                 let mut data: [u8; 128] = [0; 128];
@@ -67,34 +168,125 @@ fn server_routine(listener: &TcpListener, config: &mut Config, config_file: &Str
                         .map_err(|e| Error::IO(e.to_string()))?;
                     continue;
                 }
-                let (client_cmd, client_arg, client_process) =
-                    match parse_client_input(config, &formatted) {
-                        Ok((client_cmd, client_arg, client_process)) => {
-                            (client_cmd, client_arg, client_process)
-                        }
+                let words: Vec<&str> = formatted.split_whitespace().collect();
+                let config_reply: Option<String> = match words.as_slice() {
+                    ["reload"] => Some(reload_config(config, config_file)),
+                    ["avail"] => Some(config.avail(config_file).unwrap_or_else(|e| e.to_string())),
+                    ["update"] => Some(reload_config(config, config_file)),
+                    ["update", job_names @ ..] => {
+                        let job_names: Vec<String> =
+                            job_names.iter().map(|name| name.to_string()).collect();
+                        Some(config.update(config_file, &job_names).unwrap_or_else(|e| {
+                            format!("Can't update, the previous config is kept: {e}")
+                        }))
+                    }
+                    ["config", "diff"] => Some(
+                        config
+                            .config_diff(config_file)
+                            .unwrap_or_else(|e| e.to_string()),
+                    ),
+                    _ => None,
+                };
+                if let Some(reply) = config_reply {
+                    s.write(&reply.into_bytes())
+                        .map_err(|e| Error::IO(e.to_string()))?;
+                    continue;
+                }
+                let input: ClientInput = match parse_client_input(config, &formatted) {
+                    Ok(input) => input,
+                    Err(e) => {
+                        s.write(&e.to_string().into_bytes())
+                            .map_err(|e| Error::IO(e.to_string()))?;
+                        continue;
+                    }
+                };
+                let rolling: Result<Option<u32>> = match input.cmd.as_str() {
+                    "restart" => parse_rolling_from_client_input(&input.flags),
+                    _ if input.flags.is_empty() => Ok(None),
+                    _ => Err(Error::ParseClientInput(format!(
+                        "{} has no options",
+                        input.cmd
+                    ))),
+                };
+                let rolling: Option<u32> = match rolling {
+                    Ok(rolling) => rolling,
+                    Err(e) => {
+                        s.write(&e.to_string().into_bytes())
+                            .map_err(|e| Error::IO(e.to_string()))?;
+                        continue;
+                    }
+                };
+                let operation_kind: Option<OperationKind> = match (input.cmd.as_str(), rolling) {
+                    ("start", _) => Some(OperationKind::Start),
+                    ("stop", _) => Some(OperationKind::Stop),
+                    ("restart", _) => Some(OperationKind::Restart),
+                    _ => None,
+                };
+                let mut replies: Vec<Reply> = Vec::new();
+                if input.cmd == "status" {
+                    for group in input.groups.iter() {
+                        replies.push(Reply::Done(config.group_status(group)));
+                    }
+                }
+                for target in input.targets.iter() {
+                    let (client_arg, client_process) = match target {
+                        Ok((client_arg, client_process)) => (client_arg, *client_process),
                         Err(e) => {
-                            s.write(&e.to_string().into_bytes())
-                                .map_err(|e| Error::IO(e.to_string()))?;
+                            replies.push(Reply::Done(e.to_string()));
                             continue;
                         }
                     };
-                let job: &mut Job = config.get_mut(&client_arg).unwrap();
-                let ret = match client_cmd.as_str() {
-                    "start" => job.start(&client_arg, client_process),
-                    "stop" => job.stop(&client_arg, client_process),
-                    "restart" => job.restart(&client_arg, client_process),
-                    "status" => job.status(&client_arg, client_process),
-                    _ => Err(Error::CommandIsNotSupported(
-                        "Unknown command: Please try start, stop or restart!".into(),
-                    )),
-                };
-                if ret.is_err() {
-                    s.write(&ret.unwrap_err().to_string().into_bytes())
-                        .map_err(|e| Error::IO(e.to_string()))?;
-                } else {
-                    s.write(&ret.unwrap().into_bytes())
-                        .map_err(|e| Error::IO(e.to_string()))?;
+                    let Some(job) = config.get_mut(client_arg) else {
+                        replies.push(Reply::Done(format!("{client_arg} is removed")));
+                        continue;
+                    };
+                    if let Some(kind) = operation_kind {
+                        let queued: Result<Vec<u64>> = match rolling {
+                            Some(batch_size) => job
+                                .rolling_restart(client_arg, client_process, batch_size)
+                                .map(|id| vec![id]),
+                            None => job.queue_operation(client_arg, client_process, kind),
+                        };
+                        replies.push(match queued {
+                            Ok(ids) => Reply::Waiting {
+                                job_name: client_arg.clone(),
+                                kind,
+                                ids,
+                                results: Vec::new(),
+                            },
+                            Err(e) => Reply::Done(e.to_string()),
+                        });
+                        continue;
+                    }
+                    let ret = match input.cmd.as_str() {
+                        "status" => job.status(client_arg, client_process),
+                        "scale" => parse_num_procs_from_client_input(&input.extra)
+                            .and_then(|num_procs| job.scale(client_arg, num_procs)),
+                        "write" => job.write_stdin(
+                            client_arg,
+                            client_process,
+                            &parse_stdin_data_from_client_input(&input.extra),
+                        ),
+                        "closestdin" => job.close_stdin(client_arg, client_process),
+                        "attach" if input.targets.len() > 1 => Err(Error::AttachJobFail(
+                            "Target a single process".into(),
+                        )),
+                        "attach" => s
+                            .try_clone()
+                            .map_err(|e| Error::IO(e.to_string()))
+                            .and_then(|stream| job.attach(client_arg, client_process, stream)),
+                        _ => Err(Error::CommandIsNotSupported(
+                            "Unknown command: Please try start, stop, restart, scale, write, closestdin or attach!"
+                                .into(),
+                        )),
+                    };
+                    match ret {
+                        Ok(message) => replies.push(Reply::Done(message)),
+                        Err(e) => replies.push(Reply::Done(e.to_string())),
+                    }
                 }
+                pending_replies.push(PendingReply { stream: s, replies });
+                send_finished_replies(&mut pending_replies, config);
             }
             Err(ref e) if e.kind() == ErrorKind::WouldBlock => sleep(duration),
             Err(e) => return Err(Error::IO(e.to_string())),
@@ -103,6 +295,18 @@ fn server_routine(listener: &TcpListener, config: &mut Config, config_file: &Str
     Ok(())
 }
 
+// One line per result, the status messages already end with a new line
+fn join_results(results: &Vec<String>) -> String {
+    let mut ret: String = String::new();
+    for result in results {
+        if !ret.is_empty() && !ret.ends_with('\n') {
+            ret.push('\n');
+        }
+        ret.push_str(result);
+    }
+    ret
+}
+
 fn init_connection(ip: String, port: String) -> Result<TcpListener> {
     let listener =
         TcpListener::bind(format!("{ip}:{port}")).map_err(|err| Error::Default(err.to_string()))?;
@@ -124,6 +328,8 @@ fn main() -> Result<()> {
     let config_file: String = args().nth(1).unwrap();
     let mut config: Config = Config::new();
     config.parse_config_file(&config_file)?;
+    config.apply_subreaper()?;
+    config.adopt_processes(STATE_FILE);
     config.run_autostart_jobs();
     println!("{:#?}", config);
     unsafe {
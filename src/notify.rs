@@ -0,0 +1,359 @@
+use crate::config::RawConfig;
+use crate::error::{Error, Result};
+use crate::glob::glob_match;
+use crate::job::ProcessStates;
+use crate::logger::log;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(2);
+// delay before the first retry, doubled after each failure
+const RETRY_DELAY_SECS: u64 = 1;
+
+#[derive(Debug, Clone)]
+pub struct ProcessEvent {
+    pub job_name: String,
+    pub process_index: usize,
+    pub from_state: ProcessStates,
+    pub state: ProcessStates,
+    pub pid: Option<u32>,
+    pub at: SystemTime,
+}
+
+impl ProcessEvent {
+    fn timestamp(&self) -> u64 {
+        self.at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    pub fn to_json(&self) -> String {
+        let pid: String = match self.pid {
+            Some(pid) => pid.to_string(),
+            None => String::from("null"),
+        };
+        format!(
+            "{{\"job\":{},\"process\":{},\"from_state\":\"{}\",\"state\":\"{}\",\"pid\":{pid},\"timestamp\":{}}}",
+            _json_string(&self.job_name),
+            self.process_index,
+            self.from_state.as_str(),
+            self.state.as_str(),
+            self.timestamp()
+        )
+    }
+
+    fn environment(&self) -> Vec<(String, String)> {
+        vec![
+            ("TASKMASTER_EVENT_JOB".into(), self.job_name.clone()),
+            (
+                "TASKMASTER_EVENT_PROCESS".into(),
+                self.process_index.to_string(),
+            ),
+            (
+                "TASKMASTER_EVENT_FROM_STATE".into(),
+                self.from_state.as_str().into(),
+            ),
+            ("TASKMASTER_EVENT_STATE".into(), self.state.as_str().into()),
+            (
+                "TASKMASTER_EVENT_PID".into(),
+                self.pid.map(|pid| pid.to_string()).unwrap_or_default(),
+            ),
+            (
+                "TASKMASTER_EVENT_TIMESTAMP".into(),
+                self.timestamp().to_string(),
+            ),
+        ]
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum NotifyTarget {
+    Command(String),
+    Url(String),
+}
+
+// `[notify:name]` section of the config
+#[derive(Debug, PartialEq, Clone)]
+pub struct NotifyRule {
+    // patterns of the job names
+    pub jobs: Vec<String>,
+    pub states: Vec<ProcessStates>,
+    pub target: NotifyTarget,
+    pub retries: u32,
+}
+
+impl NotifyRule {
+    fn matches(&self, event: &ProcessEvent) -> bool {
+        self.states.contains(&event.state)
+            && self
+                .jobs
+                .iter()
+                .any(|pattern| glob_match(pattern, &event.job_name))
+    }
+}
+
+#[derive(Debug)]
+pub struct Delivery {
+    rule_name: String,
+    event: ProcessEvent,
+    attempts: u32,
+    next_attempt: Instant,
+    // exec target still running
+    child: Option<Child>,
+    // url target being posted by a thread, so a slow server doesn't block
+    // the daemon
+    request: Option<Receiver<Result<()>>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Notifier {
+    pub rules: HashMap<String, NotifyRule>,
+    pub queue: Vec<Delivery>,
+}
+
+impl Clone for Notifier {
+    fn clone(&self) -> Self {
+        Notifier {
+            rules: self.rules.clone(),
+            queue: Vec::new(),
+        }
+    }
+}
+
+impl Notifier {
+    pub fn notify(&mut self, event: &ProcessEvent) {
+        for (rule_name, rule) in self.rules.iter() {
+            if rule.matches(event) {
+                self.queue.push(Delivery {
+                    rule_name: rule_name.clone(),
+                    event: event.clone(),
+                    attempts: 0,
+                    next_attempt: Instant::now(),
+                    child: None,
+                    request: None,
+                });
+            }
+        }
+    }
+
+    // Pids of the exec targets still running
+    pub fn child_pids(&self) -> Vec<u32> {
+        self.queue
+            .iter()
+            .filter_map(|delivery| delivery.child.as_ref().map(Child::id))
+            .collect()
+    }
+
+    // Send the queued notifications, failed ones are retried later
+    pub fn routine(&mut self) {
+        let rules: &HashMap<String, NotifyRule> = &self.rules;
+        self.queue.retain_mut(|delivery| {
+            let Some(rule) = rules.get(&delivery.rule_name) else {
+                return false;
+            };
+            let result: Option<Result<()>> = match (delivery.child.as_mut(), &delivery.request) {
+                (Some(child), _) => match child.try_wait() {
+                    Ok(None) => return true,
+                    Ok(Some(status)) if status.success() => Some(Ok(())),
+                    Ok(Some(status)) => Some(Err(Error::NotifyFail(status.to_string()))),
+                    Err(e) => Some(Err(Error::NotifyFail(e.to_string()))),
+                },
+                (None, Some(request)) => match request.try_recv() {
+                    Err(TryRecvError::Empty) => return true,
+                    Ok(result) => Some(result),
+                    Err(TryRecvError::Disconnected) => {
+                        Some(Err(Error::NotifyFail("request thread is gone".into())))
+                    }
+                },
+                (None, None) if delivery.next_attempt > Instant::now() => return true,
+                (None, None) => {
+                    delivery.attempts += 1;
+                    match &rule.target {
+                        NotifyTarget::Url(url) => {
+                            let (sender, receiver) = mpsc::channel();
+                            let (url, body) = (url.clone(), delivery.event.to_json());
+                            thread::spawn(move || sender.send(post_json(&url, &body)));
+                            delivery.request = Some(receiver);
+                            None
+                        }
+                        NotifyTarget::Command(command_line) => {
+                            match _spawn_command(command_line, &delivery.event) {
+                                Ok(child) => {
+                                    delivery.child = Some(child);
+                                    None
+                                }
+                                Err(e) => Some(Err(e)),
+                            }
+                        }
+                    }
+                }
+            };
+            match result {
+                None => true,
+                Some(Ok(())) => false,
+                Some(Err(e)) => {
+                    delivery.child = None;
+                    delivery.request = None;
+                    let event: &ProcessEvent = &delivery.event;
+                    log(&format!(
+                        "ERROR: notify:{} {}:{} {}: attempt {} failed: {e}",
+                        delivery.rule_name,
+                        event.job_name,
+                        event.process_index,
+                        event.state.as_str(),
+                        delivery.attempts
+                    ));
+                    if delivery.attempts > rule.retries {
+                        log(&format!(
+                            "ERROR: notify:{} giving up after {} attempts",
+                            delivery.rule_name, delivery.attempts
+                        ));
+                        return false;
+                    }
+                    let delay: u64 = RETRY_DELAY_SECS << (delivery.attempts - 1).min(6);
+                    delivery.next_attempt = Instant::now() + Duration::from_secs(delay);
+                    true
+                }
+            }
+        });
+    }
+}
+
+pub fn parse_notify_rule(raw: &RawConfig) -> Result<NotifyRule> {
+    let get = |field_name: &str| match raw.get(field_name) {
+        Some(Some(value)) if !value.trim().is_empty() => Some(value.trim().to_string()),
+        _ => None,
+    };
+    let bad_format = |field_name: &str, msg: String| Error::FieldBadFormat {
+        field_name: field_name.into(),
+        msg,
+    };
+    let jobs: Vec<String> = match get("jobs") {
+        Some(jobs) => jobs.split(",").map(str::trim).map(String::from).collect(),
+        None => vec![String::from("*")],
+    };
+    let Some(raw_states) = get("states") else {
+        return Err(bad_format("states", "Field is not set".into()));
+    };
+    let states: Vec<ProcessStates> = raw_states
+        .split(",")
+        .map(str::trim)
+        .map(|s| ProcessStates::from_name(s).ok_or_else(|| bad_format("states", s.into())))
+        .collect::<Result<Vec<ProcessStates>>>()?;
+    let target: NotifyTarget = match (get("command"), get("url")) {
+        (Some(command), None) => NotifyTarget::Command(command),
+        (None, Some(url)) => {
+            _parse_url(&url)?;
+            NotifyTarget::Url(url)
+        }
+        _ => return Err(bad_format("command", "Set either command or url".into())),
+    };
+    let retries: u32 = match get("retries") {
+        Some(retries) => retries.parse::<u32>().map_err(|_| Error::CantParseField {
+            field_name: "retries".into(),
+            value: retries.clone(),
+            type_name: std::any::type_name::<u32>().into(),
+        })?,
+        None => 3,
+    };
+    Ok(NotifyRule {
+        jobs,
+        states,
+        target,
+        retries,
+    })
+}
+
+// POST a JSON body to an `http://host[:port]/path` url, a 2xx status is a
+// success
+pub fn post_json(url: &str, body: &str) -> Result<()> {
+    let to_error = |e: std::io::Error| Error::NotifyFail(format!("{url}: {e}"));
+    let (host, port, path) = _parse_url(url)?;
+    let address = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(to_error)?
+        .next()
+        .ok_or_else(|| Error::NotifyFail(format!("{url}: can't resolve {host}")))?;
+    let mut stream: TcpStream =
+        TcpStream::connect_timeout(&address, HTTP_TIMEOUT).map_err(to_error)?;
+    stream
+        .set_read_timeout(Some(HTTP_TIMEOUT))
+        .map_err(to_error)?;
+    stream
+        .set_write_timeout(Some(HTTP_TIMEOUT))
+        .map_err(to_error)?;
+    let request: String = format!(
+        "POST {path} HTTP/1.1\r\n\
+         Host: {host}:{port}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).map_err(to_error)?;
+    let mut response: [u8; 64] = [0; 64];
+    let bytes_read: usize = stream.read(&mut response).map_err(to_error)?;
+    let status_line: String = String::from_utf8_lossy(&response[..bytes_read]).into_owned();
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        Some(code) => Err(Error::NotifyFail(format!("{url}: HTTP status {code}"))),
+        None => Err(Error::NotifyFail(format!("{url}: bad HTTP response"))),
+    }
+}
+
+// Private
+
+fn _parse_url(url: &str) -> Result<(String, u16, String)> {
+    let bad_url = || Error::FieldBadFormat {
+        field_name: "url".into(),
+        msg: format!("{url} is not an http://host[:port]/path url"),
+    };
+    let rest: &str = url.strip_prefix("http://").ok_or_else(bad_url)?;
+    let (authority, path) = match rest.find('/') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse::<u16>().map_err(|_| bad_url())?),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(bad_url());
+    }
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+fn _spawn_command(command_line: &str, event: &ProcessEvent) -> Result<Child> {
+    Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command_line)
+        .envs(event.environment())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| Error::NotifyFail(format!("{command_line}: {e}")))
+}
+
+fn _json_string(value: &str) -> String {
+    let mut json: String = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
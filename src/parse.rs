@@ -1,45 +1,254 @@
-use std::{any::type_name, collections::HashMap, str::FromStr};
+use std::{
+    any::type_name,
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+};
 
 use crate::{
+    activation::{parse_socket_address, SocketAddress},
+    capabilities::{capability_number, CAPABILITY_NAMES},
     config::{Config, RawConfig},
     error::{Error, Result},
-    job::{AutorestartOptions, Job, ProcessInfo, StopSignals},
+    eventlistener::EVENT_TYPES,
+    expand::{expand_variables, validation_lookup},
+    glob::{glob_match, is_glob},
+    job::{
+        AutorestartOptions, IoPriorityClass, Job, ProcessInfo, ReadyOptions, StdinOptions,
+        StopSignals,
+    },
+    scheduling::MAX_CPUS,
 };
 
-pub fn parse_client_input(
-    config: &mut Config,
-    raw: &String,
-) -> Result<(String, String, Option<usize>)> {
-    let client_cmd = _parse_cmd_from_client_input(&raw)?;
-    let client_arg = _parse_arg_from_client_input(&raw)?;
-    let client_process = _parse_target_process_number_from_client_input(&raw)?;
-    if config.contains_key(&client_arg) {
-        Ok((client_cmd, client_arg, client_process))
-    } else {
-        Err(Error::ParseClientInput("Job not found...".into()))
+// commands that accept several targets, e.g. `restart api worker:2`
+const MULTI_TARGET_COMMANDS: [&str; 4] = ["start", "stop", "restart", "status"];
+
+// job name with an optional process index
+pub type Target = (String, Option<usize>);
+
+pub struct ClientInput {
+    pub cmd: String,
+    // jobs the command is run on, with an optional process index, or the
+    // error of a target that matches no job
+    pub targets: Vec<Result<Target>>,
+    // groups named by `group:*` and `group:job` targets
+    pub groups: Vec<String>,
+    // `--option` arguments of the commands that accept several targets
+    pub flags: Vec<String>,
+    pub extra: Option<String>,
+}
+
+pub fn parse_client_input(config: &mut Config, raw: &String) -> Result<ClientInput> {
+    let cmd = _parse_cmd_from_client_input(raw)?;
+    let multi_target: bool = MULTI_TARGET_COMMANDS.contains(&cmd.as_str());
+    let (flags, args): (Vec<&str>, Vec<&str>) = match multi_target {
+        true => raw
+            .split_whitespace()
+            .skip(1)
+            .partition(|arg| arg.starts_with("--")),
+        false => (Vec::new(), raw.split_whitespace().skip(1).take(1).collect()),
+    };
+    if args.is_empty() {
+        return Err(Error::ParseClientInput("Job is not set...".into()));
+    }
+    let mut input: ClientInput = ClientInput {
+        cmd,
+        targets: Vec::new(),
+        groups: Vec::new(),
+        flags: flags.into_iter().map(String::from).collect(),
+        extra: match multi_target {
+            true => None,
+            false => _parse_extra_from_client_input(raw),
+        },
+    };
+    for arg in args {
+        match _parse_group_target_from_client_input(config, arg) {
+            Some(Ok((group, targets))) => {
+                if !input.groups.contains(&group) {
+                    input.groups.push(group);
+                }
+                input.targets.extend(targets.into_iter().map(Ok));
+            }
+            Some(Err(e)) => input.targets.push(Err(e)),
+            None => input
+                .targets
+                .extend(_parse_job_target_from_client_input(config, arg)),
+        }
+    }
+    Ok(input)
+}
+
+pub fn parse_num_procs_from_client_input(extra: &Option<String>) -> Result<u32> {
+    match extra.as_ref().map(|s| s.parse::<u32>()) {
+        Some(Ok(num_procs)) => Ok(num_procs),
+        _ => Err(Error::ParseClientInput(
+            "Wrong format for the number of process...".into(),
+        )),
+    }
+}
+
+// Data of `write`: `\n`, `\t` and `\\` are escapes and a line feed is added,
+// `\c` ends the data without it, as with `echo -e`
+pub fn parse_stdin_data_from_client_input(extra: &Option<String>) -> String {
+    let mut data: String = String::new();
+    let mut chars = extra.as_deref().unwrap_or_default().chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            data.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => data.push('\n'),
+            Some('t') => data.push('\t'),
+            Some('\\') => data.push('\\'),
+            Some('c') => return data,
+            Some(other) => {
+                data.push('\\');
+                data.push(other);
+            }
+            None => data.push('\\'),
+        }
+    }
+    data.push('\n');
+    data
+}
+
+// `--rolling [--batch=N]` of restart: number of processes restarted at a time
+pub fn parse_rolling_from_client_input(flags: &Vec<String>) -> Result<Option<u32>> {
+    let mut rolling: bool = false;
+    let mut batch: Option<u32> = None;
+    for flag in flags {
+        match (
+            flag.as_str(),
+            flag.strip_prefix("--batch=").map(str::parse::<u32>),
+        ) {
+            ("--rolling", _) => rolling = true,
+            (_, Some(Ok(size))) if size > 0 => batch = Some(size),
+            _ => return Err(Error::ParseClientInput(format!("Unknown option: {flag}"))),
+        }
+    }
+    match (rolling, batch) {
+        (false, Some(_)) => Err(Error::ParseClientInput("--batch needs --rolling".into())),
+        (false, None) => Ok(None),
+        (true, batch) => Ok(Some(batch.unwrap_or(1))),
     }
 }
 
 pub fn parse_job(raw: &RawConfig) -> Result<Job> {
     let num_procs: u32 = parse_num_procs(&raw)?;
-    Ok(Job {
-        command: _parse_command(&raw)?,
-        arguments: _parse_arguments(&raw)?,
+    let shell: bool = _parse_shell(raw)?;
+    let job: Job = Job {
+        command: _parse_command(raw, shell)?,
+        arguments: _parse_arguments(raw, shell)?,
+        shell,
         num_procs,
         auto_start: parse_autostart(&raw)?,
         auto_restart: parse_autorestart(&raw)?,
         exit_codes: _parse_exitcodes(&raw)?,
         start_secs: _parse_start_secs(&raw)?,
+        ready: _parse_ready(raw)?,
+        watchdog_secs: _parse_watchdog_secs(raw)?,
+        heartbeat_file: _parse_heartbeat_file(raw)?,
         start_retries: _parse_start_retries(&raw)?,
         stop_signal: _parse_stop_signal(&raw)?,
         stop_wait_secs: _parse_stop_wait_seconds(&raw)?,
         stderr_file: _parse_stderr_file(&raw)?,
         stdout_file: _parse_stdout_file(&raw)?,
+        stdin: _parse_stdin(raw)?,
+        pty: _parse_pty(raw)?,
         environment: _parse_environment(&raw)?,
+        clean_environment: _parse_clean_environment(raw)?,
+        environment_allowlist: _parse_environment_allowlist(raw)?,
+        environment_file: _parse_environment_file(raw)?,
         work_dir: _parse_working_directory(&raw)?,
         umask: _parse_umask(&raw)?,
+        watch: _parse_watch(raw)?,
+        watch_debounce_ms: _parse_watch_debounce_ms(raw)?,
+        pre_start: _parse_hook(raw, "pre_start")?,
+        post_start: _parse_hook(raw, "post_start")?,
+        pre_stop: _parse_hook(raw, "pre_stop")?,
+        post_stop: _parse_hook(raw, "post_stop")?,
+        hook_timeout_secs: _parse_hook_timeout_secs(raw)?,
+        events: None,
+        buffer_size: Job::default().buffer_size,
+        private_network: _parse_private_network(raw)?,
+        private_tmp: _parse_private_tmp(raw)?,
+        private_pid: _parse_private_pid(raw)?,
+        read_only_paths: _parse_read_only_paths(raw)?,
+        no_new_privs: _parse_no_new_privs(raw)?,
+        capabilities: _parse_capabilities(raw, "capabilities")?,
+        ambient_capabilities: _parse_ambient_capabilities(raw)?,
+        nice: _parse_ranged_field(raw, "nice", -20, 19)?,
+        cpu_affinity: _parse_cpu_affinity(raw)?,
+        ioprio_class: _parse_ioprio_class(raw)?,
+        ioprio_level: _parse_ioprio_level(raw)?,
+        oom_score_adj: _parse_ranged_field(raw, "oom_score_adj", -1000, 1000)?,
+        pidfile: _parse_pidfile(raw)?,
+        socket: _parse_socket(raw)?,
+        socket_lazy: _parse_socket_lazy(raw)?,
+        config_dir: None,
         processes: vec![ProcessInfo::default(); num_procs as usize],
-    })
+        watcher: None,
+        watch_changed_at: None,
+        running_hooks: Vec::new(),
+        event_queue: VecDeque::new(),
+        pool_serial: 0,
+        listen_socket: None,
+        rolling_restart: None,
+        finished_operations: Vec::new(),
+    };
+    _check_variables(&job)?;
+    Ok(job)
+}
+
+// `[eventlistener:x]` section: a job whose stdin and stdout are the event
+// protocol channel
+pub fn parse_event_listener(raw: &RawConfig) -> Result<Job> {
+    let mut job: Job = parse_job(raw)?;
+    for (field_name, is_set) in [
+        ("stdout", job.stdout_file.is_some()),
+        ("stdin", job.stdin != Job::default().stdin),
+        ("pty", job.pty),
+    ] {
+        if is_set {
+            return Err(Error::FieldBadFormat {
+                field_name: field_name.into(),
+                msg: "stdin and stdout of an event listener are the event channel".into(),
+            });
+        }
+    }
+    job.stdin = StdinOptions::Pipe;
+    job.events = Some(_parse_events(raw)?);
+    job.buffer_size = _parse_buffer_size(raw)?;
+    Ok(job)
+}
+
+// `programs` of a `[group:name]` section
+pub fn parse_group(raw: &RawConfig) -> Result<Vec<String>> {
+    let field_name: String = String::from("programs");
+    let Some(Some(raw_programs)) = raw.get(&field_name) else {
+        return Err(Error::FieldBadFormat {
+            field_name,
+            msg: "Field is not set".into(),
+        });
+    };
+    // section names, hence job names, are lowercased by the config parser
+    let programs: Vec<String> = raw_programs
+        .split(",")
+        .map(str::trim)
+        .map(str::to_lowercase)
+        .collect();
+    if programs.iter().any(|program| program.is_empty()) {
+        return Err(Error::FieldBadFormat {
+            field_name,
+            msg: raw_programs.clone(),
+        });
+    }
+    Ok(programs)
+}
+
+// `[taskmasterd]` section: options of the daemon itself, returns `subreaper`
+pub fn parse_daemon_options(raw: &RawConfig) -> Result<bool> {
+    _parse_raw_config_field::<bool>(raw, String::from("subreaper"), false)
 }
 
 // Private
@@ -52,34 +261,110 @@ fn _parse_cmd_from_client_input(raw: &String) -> Result<String> {
     }
 }
 
-fn _parse_arg_from_client_input(raw: &String) -> Result<String> {
-    if let Some(cmd) = raw.split_whitespace().skip(1).next() {
-        let index = cmd.rfind(":");
-        if index.is_some() {
-            let split_cmd = &cmd[0..index.unwrap()];
-            Ok(split_cmd.to_string())
+// `job[:n]`, `all` or a pattern such as `web*[:n]`
+fn _parse_job_target_from_client_input(config: &Config, arg: &str) -> Vec<Result<Target>> {
+    let pattern: String = _parse_job_name_from_target(arg);
+    let process: Option<usize> = match _parse_process_number_from_target(arg) {
+        Ok(process) => process,
+        Err(e) => return vec![Err(e)],
+    };
+    let job_names: Vec<String> = match pattern.as_str() {
+        _ if config.job_names().contains(&pattern) => vec![pattern.clone()],
+        "all" => config.job_names(),
+        _ if is_glob(&pattern) => config
+            .job_names()
+            .into_iter()
+            .filter(|job_name| glob_match(&pattern, job_name))
+            .collect(),
+        _ => Vec::new(),
+    };
+    if job_names.is_empty() {
+        return vec![Err(Error::ParseClientInput(format!(
+            "{arg}: Job not found..."
+        )))];
+    }
+    job_names
+        .into_iter()
+        .map(|job_name| Ok((job_name, process)))
+        .collect()
+}
+
+fn _parse_job_name_from_target(target: &str) -> String {
+    match target.rfind(":") {
+        Some(index) => target[0..index].to_string(),
+        None => target.to_string(),
+    }
+}
+
+// `group:*` targets every job of the group, `group:job[:n]` one of them
+fn _parse_group_target_from_client_input(
+    config: &Config,
+    arg: &str,
+) -> Option<Result<(String, Vec<Target>)>> {
+    let (group, member) = arg.split_once(":")?;
+    let members: &Vec<String> = config.group_members(group)?;
+    if member == "*" {
+        let targets = members.iter().map(|job_name| (job_name.clone(), None));
+        return Some(Ok((group.to_string(), targets.collect())));
+    }
+    let job_name: String = _parse_job_name_from_target(member);
+    if !members.contains(&job_name) {
+        return Some(Err(Error::ParseClientInput(format!(
+            "{job_name} is not in group {group}..."
+        ))));
+    }
+    Some(
+        _parse_process_number_from_target(member)
+            .map(|process| (group.to_string(), vec![(job_name, process)])),
+    )
+}
+
+fn _parse_process_number_from_target(target: &str) -> Result<Option<usize>> {
+    if let Some(index) = target.rfind(":") {
+        let split_cmd = &target[index + 1..];
+        if let Ok(number) = split_cmd.parse::<usize>() {
+            return Ok(Some(number));
         } else {
-            Ok(cmd.to_string())
+            return Err(Error::ParseClientInput(
+                "Wrong format for the number of process...".into(),
+            ));
         }
+    }
+    Ok(None)
+}
+
+// Everything after the job name, e.g. the `3` of `scale web 3`
+fn _parse_extra_from_client_input(raw: &str) -> Option<String> {
+    let raw: &str = raw.trim();
+    let after_cmd: &str = raw[raw.find(char::is_whitespace)?..].trim_start();
+    let extra: &str = after_cmd[after_cmd.find(char::is_whitespace)?..].trim();
+    if extra.is_empty() {
+        None
     } else {
-        Err(Error::ParseClientInput("Job is not set...".into()))
+        Some(extra.to_string())
     }
 }
 
-fn _parse_target_process_number_from_client_input(raw: &String) -> Result<Option<usize>> {
-    if let Some(cmd) = raw.split_whitespace().skip(1).next() {
-        if let Some(index) = cmd.rfind(":") {
-            let split_cmd = &cmd[index + 1..];
-            if let Ok(number) = split_cmd.parse::<usize>() {
-                return Ok(Some(number));
-            } else {
-                return Err(Error::ParseClientInput(
-                    "Wrong format for the number of process...".into(),
-                ));
-            }
-        }
+// Placeholders are expanded per process when it starts, make sure they are
+// valid as soon as the config is loaded
+fn _check_variables(job: &Job) -> Result<()> {
+    let mut fields: Vec<&String> = vec![&job.command];
+    fields.extend(job.arguments.iter().flatten());
+    fields.extend(job.stdout_file.iter());
+    fields.extend(job.stderr_file.iter());
+    fields.extend(job.work_dir.iter());
+    fields.extend(job.environment_file.iter());
+    fields.extend(job.watch.iter().flatten());
+    fields.extend(job.read_only_paths.iter().flatten());
+    fields.extend(job.pre_start.iter());
+    fields.extend(job.post_start.iter());
+    fields.extend(job.pre_stop.iter());
+    fields.extend(job.post_stop.iter());
+    fields.extend(job.environment.iter().flat_map(|env| env.values()));
+    for field in fields {
+        expand_variables(field, validation_lookup)?;
     }
-    Ok(None)
+    Ok(())
 }
 
 fn _parse_raw_config_field<T: FromStr>(
@@ -134,37 +419,336 @@ fn _parse_command_line(raw: &RawConfig) -> Result<String> {
     }
 }
 
-fn _parse_arguments(raw: &RawConfig) -> Result<Option<Vec<String>>> {
-    let cmd = Some(_parse_command_line(raw)?);
-    if let Some(cmd_as_str) = cmd {
-        let args: Vec<String> = cmd_as_str
-            .split_whitespace()
-            .skip(1)
-            .map(String::from)
-            .collect();
-        if args.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(args))
-        }
+fn _parse_arguments(raw: &RawConfig, shell: bool) -> Result<Option<Vec<String>>> {
+    if shell {
+        return Ok(None);
+    }
+    let args: Vec<String> = _split_shell_words(&_parse_command_line(raw)?)?
+        .into_iter()
+        .skip(1)
+        .collect();
+    if args.is_empty() {
+        Ok(None)
     } else {
-        Err(Error::FieldCommandIsNotSet)
+        Ok(Some(args))
     }
 }
 
-fn _parse_command(raw: &RawConfig) -> Result<String> {
-    let string = Some(_parse_command_line(raw)?);
-    if let Some(cmd_as_str) = string {
-        if let Some(cmd) = cmd_as_str.split_whitespace().next() {
-            Ok(cmd.to_string())
-        } else {
-            Err(Error::FieldCommandIsNotSet)
+fn _parse_command(raw: &RawConfig, shell: bool) -> Result<String> {
+    let cmd_as_str: String = _parse_command_line(raw)?;
+    if shell {
+        if cmd_as_str.trim().is_empty() {
+            return Err(Error::FieldCommandIsNotSet);
         }
+        return Ok(cmd_as_str.trim().to_string());
+    }
+    if let Some(cmd) = _split_shell_words(&cmd_as_str)?.into_iter().next() {
+        Ok(cmd)
     } else {
         Err(Error::FieldCommandIsNotSet)
     }
 }
 
+fn _parse_shell(raw: &RawConfig) -> Result<bool> {
+    _parse_raw_config_field::<bool>(raw, String::from("shell"), Job::default().shell)
+}
+
+// Split a command line into words the way a POSIX shell does, with quotes
+// and backslash escapes, but without any expansion
+fn _split_shell_words(line: &str) -> Result<Vec<String>> {
+    let bad_format = |msg: &str| Error::FieldBadFormat {
+        field_name: String::from("command"),
+        msg: msg.into(),
+    };
+    let mut words: Vec<String> = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(w) = word.take() {
+                    words.push(w);
+                }
+            }
+            '\\' => match chars.next() {
+                Some(escaped) => word.get_or_insert_with(String::new).push(escaped),
+                None => return Err(bad_format("Trailing backslash")),
+            },
+            '\'' => {
+                let w: &mut String = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => w.push(c),
+                        None => return Err(bad_format("Unterminated single quote")),
+                    }
+                }
+            }
+            '"' => {
+                let w: &mut String = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) if matches!(c, '$' | '`' | '"' | '\\') => w.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                w.push('\\');
+                                w.push(c);
+                            }
+                            None => return Err(bad_format("Unterminated double quote")),
+                        },
+                        Some(c) => w.push(c),
+                        None => return Err(bad_format("Unterminated double quote")),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(w) = word {
+        words.push(w);
+    }
+    Ok(words)
+}
+
+fn _parse_watch(raw: &RawConfig) -> Result<Option<Vec<String>>> {
+    let field_name: String = String::from("watch");
+    let Some(Some(raw_paths)) = raw.get(&field_name) else {
+        return Ok(Job::default().watch);
+    };
+    let paths: Vec<String> = raw_paths
+        .split(",")
+        .map(str::trim)
+        .map(String::from)
+        .collect();
+    if paths.iter().any(|path| path.is_empty()) {
+        return Err(Error::FieldBadFormat {
+            field_name,
+            msg: raw_paths.clone(),
+        });
+    }
+    Ok(Some(paths))
+}
+
+fn _parse_events(raw: &RawConfig) -> Result<Vec<String>> {
+    let field_name: String = String::from("events");
+    let Some(Some(raw_events)) = raw.get(&field_name) else {
+        return Err(Error::FieldBadFormat {
+            field_name,
+            msg: "Field is not set".into(),
+        });
+    };
+    let events: Vec<String> = raw_events
+        .split(",")
+        .map(str::trim)
+        .map(String::from)
+        .collect();
+    if let Some(event) = events
+        .iter()
+        .find(|event| !EVENT_TYPES.contains(&event.as_str()))
+    {
+        return Err(Error::FieldBadFormat {
+            field_name,
+            msg: format!("Unknown event type {event}"),
+        });
+    }
+    Ok(events)
+}
+
+fn _parse_buffer_size(raw: &RawConfig) -> Result<u32> {
+    let buffer_size: u32 = _parse_raw_config_field::<u32>(
+        raw,
+        String::from("buffer_size"),
+        Job::default().buffer_size,
+    )?;
+    if buffer_size == 0 {
+        return Err(Error::FieldBadFormat {
+            field_name: String::from("buffer_size"),
+            msg: "Must be at least 1".into(),
+        });
+    }
+    Ok(buffer_size)
+}
+
+fn _parse_private_network(raw: &RawConfig) -> Result<bool> {
+    _parse_raw_config_field::<bool>(
+        raw,
+        String::from("private_network"),
+        Job::default().private_network,
+    )
+}
+
+fn _parse_private_tmp(raw: &RawConfig) -> Result<bool> {
+    _parse_raw_config_field::<bool>(raw, String::from("private_tmp"), Job::default().private_tmp)
+}
+
+fn _parse_private_pid(raw: &RawConfig) -> Result<bool> {
+    _parse_raw_config_field::<bool>(raw, String::from("private_pid"), Job::default().private_pid)
+}
+
+fn _parse_read_only_paths(raw: &RawConfig) -> Result<Option<Vec<String>>> {
+    let field_name: String = String::from("read_only_paths");
+    let Some(Some(raw_paths)) = raw.get(&field_name) else {
+        return Ok(Job::default().read_only_paths);
+    };
+    let paths: Vec<String> = raw_paths
+        .split(",")
+        .map(str::trim)
+        .map(String::from)
+        .collect();
+    // paths are bind mounted on themselves, they must be absolute
+    if paths.iter().any(|path| !path.starts_with("/")) {
+        return Err(Error::FieldBadFormat {
+            field_name,
+            msg: format!("{raw_paths}: paths must be absolute"),
+        });
+    }
+    Ok(Some(paths))
+}
+
+fn _parse_no_new_privs(raw: &RawConfig) -> Result<bool> {
+    _parse_raw_config_field::<bool>(
+        raw,
+        String::from("no_new_privs"),
+        Job::default().no_new_privs,
+    )
+}
+
+fn _parse_capabilities(raw: &RawConfig, field_name: &str) -> Result<Option<Vec<String>>> {
+    let Some(Some(raw_capabilities)) = raw.get(field_name) else {
+        return Ok(None);
+    };
+    let mut capabilities: Vec<String> = Vec::new();
+    for name in raw_capabilities.split(",").map(str::trim) {
+        // an empty list drops every capability
+        if name.is_empty() {
+            continue;
+        }
+        let Some(number) = capability_number(name) else {
+            return Err(Error::FieldBadFormat {
+                field_name: field_name.into(),
+                msg: format!("Unknown capability {name}"),
+            });
+        };
+        capabilities.push(CAPABILITY_NAMES[number as usize].to_string());
+    }
+    Ok(Some(capabilities))
+}
+
+fn _parse_ambient_capabilities(raw: &RawConfig) -> Result<Option<Vec<String>>> {
+    let field_name: &str = "ambient_capabilities";
+    let ambient: Option<Vec<String>> = _parse_capabilities(raw, field_name)?;
+    // an ambient capability must also be permitted
+    if let (Some(ambient), Some(keep)) = (&ambient, _parse_capabilities(raw, "capabilities")?) {
+        if let Some(cap) = ambient.iter().find(|cap| !keep.contains(cap)) {
+            return Err(Error::FieldBadFormat {
+                field_name: field_name.into(),
+                msg: format!("{cap} is not in capabilities"),
+            });
+        }
+    }
+    Ok(ambient)
+}
+
+fn _parse_ranged_field(
+    raw: &RawConfig,
+    field_name: &str,
+    min: i32,
+    max: i32,
+) -> Result<Option<i32>> {
+    let Some(Some(_)) = raw.get(field_name) else {
+        return Ok(None);
+    };
+    let value: i32 = _parse_raw_config_field::<i32>(raw, field_name.into(), 0)?;
+    if value < min || value > max {
+        return Err(Error::FieldBadFormat {
+            field_name: field_name.into(),
+            msg: format!("{value} is not between {min} and {max}"),
+        });
+    }
+    Ok(Some(value))
+}
+
+// `0,2-3` is CPUs 0, 2 and 3
+fn _parse_cpu_affinity(raw: &RawConfig) -> Result<Option<Vec<usize>>> {
+    let field_name: String = String::from("cpu_affinity");
+    let Some(Some(raw_cpus)) = raw.get(&field_name) else {
+        return Ok(Job::default().cpu_affinity);
+    };
+    let bad_format = || Error::FieldBadFormat {
+        field_name: field_name.clone(),
+        msg: format!("{raw_cpus}: expected a list of CPUs such as 0,2-3"),
+    };
+    let mut cpus: Vec<usize> = Vec::new();
+    for range in raw_cpus.split(",").map(str::trim) {
+        let (first, last) = range.split_once("-").unwrap_or((range, range));
+        let first: usize = first.trim().parse::<usize>().map_err(|_| bad_format())?;
+        let last: usize = last.trim().parse::<usize>().map_err(|_| bad_format())?;
+        if first > last || last >= MAX_CPUS {
+            return Err(bad_format());
+        }
+        cpus.extend(first..=last);
+    }
+    cpus.sort();
+    cpus.dedup();
+    Ok(Some(cpus))
+}
+
+fn _parse_ioprio_class(raw: &RawConfig) -> Result<Option<IoPriorityClass>> {
+    let field_name: String = String::from("ioprio_class");
+    match raw.get(&field_name) {
+        Some(Some(s)) if s.to_lowercase() == "realtime" => Ok(Some(IoPriorityClass::Realtime)),
+        Some(Some(s)) if s.to_lowercase() == "best-effort" => Ok(Some(IoPriorityClass::BestEffort)),
+        Some(Some(s)) if s.to_lowercase() == "idle" => Ok(Some(IoPriorityClass::Idle)),
+        Some(Some(s)) => Err(Error::FieldBadFormat {
+            field_name,
+            msg: s.into(),
+        }),
+        _ => Ok(Job::default().ioprio_class),
+    }
+}
+
+fn _parse_ioprio_level(raw: &RawConfig) -> Result<Option<u32>> {
+    let field_name: &str = "ioprio_level";
+    let Some(level) = _parse_ranged_field(raw, field_name, 0, 7)? else {
+        return Ok(None);
+    };
+    // the idle class has no levels
+    if _parse_ioprio_class(raw)? == Some(IoPriorityClass::Idle) {
+        return Err(Error::FieldBadFormat {
+            field_name: field_name.into(),
+            msg: "ioprio_level can't be used with ioprio_class=idle".into(),
+        });
+    }
+    Ok(Some(level as u32))
+}
+
+fn _parse_watch_debounce_ms(raw: &RawConfig) -> Result<u32> {
+    _parse_raw_config_field::<u32>(
+        raw,
+        String::from("watch_debounce_ms"),
+        Job::default().watch_debounce_ms,
+    )
+}
+
+fn _parse_hook(raw: &RawConfig, field_name: &str) -> Result<Option<String>> {
+    match raw.get(field_name) {
+        Some(Some(command_line)) if !command_line.trim().is_empty() => {
+            Ok(Some(command_line.trim().to_string()))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn _parse_hook_timeout_secs(raw: &RawConfig) -> Result<u32> {
+    _parse_raw_config_field::<u32>(
+        raw,
+        String::from("hook_timeout_secs"),
+        Job::default().hook_timeout_secs,
+    )
+}
+
 fn _parse_umask(raw: &RawConfig) -> Result<Option<u32>> {
     let field_name: String = String::from("umask");
     let default: Option<String> = None;
@@ -215,6 +799,103 @@ fn _parse_environment(raw: &RawConfig) -> Result<Option<HashMap<String, String>>
     Ok(Some(map))
 }
 
+fn _parse_clean_environment(raw: &RawConfig) -> Result<bool> {
+    _parse_raw_config_field::<bool>(
+        raw,
+        String::from("clean_environment"),
+        Job::default().clean_environment,
+    )
+}
+
+fn _parse_environment_allowlist(raw: &RawConfig) -> Result<Option<Vec<String>>> {
+    let field_name: String = String::from("environment_allowlist");
+    let Some(Some(raw_list)) = raw.get(&field_name) else {
+        return Ok(Job::default().environment_allowlist);
+    };
+    let keys: Vec<String> = raw_list
+        .split(",")
+        .map(str::trim)
+        .map(String::from)
+        .collect();
+    if keys
+        .iter()
+        .any(|key| key.is_empty() || key.contains(char::is_whitespace))
+    {
+        return Err(Error::FieldBadFormat {
+            field_name,
+            msg: raw_list.clone(),
+        });
+    }
+    Ok(Some(keys))
+}
+
+fn _parse_environment_file(raw: &RawConfig) -> Result<Option<String>> {
+    _parse_one_word_field(
+        raw,
+        "environment_file".into(),
+        Job::default().environment_file,
+    )
+}
+
+// Read a dotenv file: `KEY=VALUE` lines, optionally prefixed by `export`.
+// Values can be single quoted (literal) or double quoted (with `\n`, `\t`,
+// `\"` and `\\` escapes). Blank lines and `#` comments are skipped.
+pub fn parse_env_file(path: &str) -> Result<HashMap<String, String>> {
+    let content: String =
+        std::fs::read_to_string(path).map_err(|e| Error::CantLoadFile(e.to_string()))?;
+    let mut map: HashMap<String, String> = HashMap::new();
+    for line in content.lines() {
+        let line: &str = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line: &str = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let Some(pos_first_equal) = line.find("=") else {
+            return Err(Error::CantParseEnvEntry(line.to_string()));
+        };
+        let key: &str = line[..pos_first_equal].trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        {
+            return Err(Error::CantParseEnvEntry(line.to_string()));
+        }
+        let raw_value: &str = line[pos_first_equal + 1..].trim();
+        let value: String = _parse_env_file_value(raw_value)
+            .ok_or_else(|| Error::CantParseEnvEntry(line.to_string()))?;
+        map.insert(key.to_string(), value);
+    }
+    Ok(map)
+}
+
+fn _parse_env_file_value(raw_value: &str) -> Option<String> {
+    if let Some(quoted) = raw_value.strip_prefix('\'') {
+        return Some(quoted[..quoted.find('\'')?].to_string());
+    }
+    if let Some(quoted) = raw_value.strip_prefix('"') {
+        let mut value: String = String::new();
+        let mut chars = quoted.chars();
+        loop {
+            match chars.next()? {
+                '"' => return Some(value),
+                '\\' => match chars.next()? {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    c => value.push(c),
+                },
+                c => value.push(c),
+            }
+        }
+    }
+    // unquoted values end at an inline comment
+    let value: &str = match raw_value.find(" #") {
+        Some(pos) => &raw_value[..pos],
+        None => raw_value,
+    };
+    Some(value.trim().to_string())
+}
+
 fn _parse_working_directory(raw: &RawConfig) -> Result<Option<String>> {
     Ok(_parse_one_word_field(
         &raw,
@@ -239,6 +920,97 @@ fn _parse_stdout_file(raw: &RawConfig) -> Result<Option<String>> {
     )?)
 }
 
+fn _parse_pidfile(raw: &RawConfig) -> Result<Option<String>> {
+    _parse_one_word_field(raw, "pidfile".into(), Job::default().pidfile)
+}
+
+fn _parse_socket(raw: &RawConfig) -> Result<Option<SocketAddress>> {
+    let field_name: String = String::from("socket");
+    let Some(raw_socket) = _parse_one_word_field(raw, field_name.clone(), None)? else {
+        return Ok(Job::default().socket);
+    };
+    match parse_socket_address(&raw_socket) {
+        Some(address) => Ok(Some(address)),
+        None => Err(Error::FieldBadFormat {
+            field_name,
+            msg: format!("{raw_socket} is not tcp://host:port or unix:///path"),
+        }),
+    }
+}
+
+fn _parse_socket_lazy(raw: &RawConfig) -> Result<bool> {
+    let field_name: String = String::from("socket_lazy");
+    let socket_lazy: bool =
+        _parse_raw_config_field::<bool>(raw, field_name.clone(), Job::default().socket_lazy)?;
+    let msg: &str = match (socket_lazy, _parse_socket(raw)?, parse_autostart(raw)?) {
+        (true, None, _) => "socket_lazy needs socket",
+        (true, _, true) => "socket_lazy jobs are started by a connection, not autostart",
+        _ => return Ok(socket_lazy),
+    };
+    Err(Error::FieldBadFormat {
+        field_name,
+        msg: msg.into(),
+    })
+}
+
+fn _parse_pty(raw: &RawConfig) -> Result<bool> {
+    let pty: bool = _parse_raw_config_field::<bool>(raw, String::from("pty"), Job::default().pty)?;
+    if pty && _parse_stdin(raw)? == StdinOptions::Pipe {
+        return Err(Error::FieldBadFormat {
+            field_name: String::from("pty"),
+            msg: "pty can't be used with stdin=pipe".into(),
+        });
+    }
+    Ok(pty)
+}
+
+fn _parse_stdin(raw: &RawConfig) -> Result<StdinOptions> {
+    let field_name: String = String::from("stdin");
+    match raw.get(&field_name) {
+        Some(Some(s)) if s == "none" => Ok(StdinOptions::Null),
+        Some(Some(s)) if s == "pipe" => Ok(StdinOptions::Pipe),
+        Some(Some(s)) => Err(Error::FieldBadFormat {
+            field_name,
+            msg: s.into(),
+        }),
+        _ => Ok(Job::default().stdin),
+    }
+}
+
+fn _parse_ready(raw: &RawConfig) -> Result<ReadyOptions> {
+    let field_name: String = String::from("ready");
+    match raw.get(&field_name) {
+        Some(Some(s)) if s == "startsecs" => Ok(ReadyOptions::Startsecs),
+        Some(Some(s)) if s == "notify" => Ok(ReadyOptions::Notify),
+        Some(Some(s)) => Err(Error::FieldBadFormat {
+            field_name,
+            msg: s.into(),
+        }),
+        _ => Ok(Job::default().ready),
+    }
+}
+
+fn _parse_watchdog_secs(raw: &RawConfig) -> Result<u32> {
+    _parse_raw_config_field::<u32>(
+        raw,
+        String::from("watchdog_secs"),
+        Job::default().watchdog_secs,
+    )
+}
+
+fn _parse_heartbeat_file(raw: &RawConfig) -> Result<Option<String>> {
+    let field_name: String = String::from("heartbeat_file");
+    let heartbeat_file: Option<String> =
+        _parse_one_word_field(raw, field_name.clone(), Job::default().heartbeat_file)?;
+    if heartbeat_file.is_some() && _parse_watchdog_secs(raw)? == 0 {
+        return Err(Error::FieldBadFormat {
+            field_name,
+            msg: "heartbeat_file needs watchdog_secs".into(),
+        });
+    }
+    Ok(heartbeat_file)
+}
+
 fn _parse_stop_wait_seconds(raw: &RawConfig) -> Result<u32> {
     _parse_raw_config_field::<u32>(
         raw,
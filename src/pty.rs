@@ -0,0 +1,175 @@
+use crate::error::{Error, Result};
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, prelude::*, ErrorKind};
+use std::net::TcpStream;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+const O_RDWR: i32 = 2;
+const O_NOCTTY: i32 = 0o400;
+const O_CLOEXEC: i32 = 0o2000000;
+const O_NONBLOCK: i32 = 0o4000;
+const F_GETFL: i32 = 3;
+const F_SETFL: i32 = 4;
+const TIOCSCTTY: u64 = 0x540E;
+// Ctrl-], same as telnet
+const DETACH_KEY: u8 = 0x1d;
+// output kept to be replayed when a client attaches
+const BACKLOG_SIZE: usize = 4096;
+
+extern "C" {
+    fn posix_openpt(flags: i32) -> i32;
+    fn grantpt(fd: i32) -> i32;
+    fn unlockpt(fd: i32) -> i32;
+    fn ptsname_r(fd: i32, buf: *mut u8, buflen: usize) -> i32;
+    fn setsid() -> i32;
+    fn ioctl(fd: i32, request: u64, ...) -> i32;
+    fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+}
+
+// Master side of the pseudo-terminal of a process, owned by the daemon
+#[derive(Debug)]
+pub struct PtySession {
+    master: File,
+    log_file: Option<File>,
+    attached: Option<TcpStream>,
+    backlog: Vec<u8>,
+}
+
+impl PtySession {
+    // Open a new pseudo-terminal and make it the controlling terminal and the
+    // stdin, stdout and stderr of the command
+    pub fn spawn_on_pty(command: &mut Command, log_file: Option<File>) -> Result<PtySession> {
+        let (master, slave) = _open_pty().map_err(|e| Error::StartJobFail(e.to_string()))?;
+        let to_error = |e: io::Error| Error::StartJobFail(e.to_string());
+        command.stdin(Stdio::from(slave.try_clone().map_err(to_error)?));
+        command.stdout(Stdio::from(slave.try_clone().map_err(to_error)?));
+        command.stderr(Stdio::from(slave));
+        unsafe {
+            command.pre_exec(|| {
+                if setsid() == -1 || ioctl(0, TIOCSCTTY, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(PtySession {
+            master,
+            log_file,
+            attached: None,
+            backlog: Vec::new(),
+        })
+    }
+
+    pub fn attach(&mut self, stream: TcpStream) -> Result<()> {
+        stream
+            .set_nonblocking(true)
+            .map_err(|e| Error::IO(e.to_string()))?;
+        self.detach("another client attached");
+        self.attached = Some(stream);
+        self._send_to_client(b"[attached: press Ctrl-] to detach]\r\n");
+        let backlog: Vec<u8> = self.backlog.clone();
+        self._send_to_client(&backlog);
+        Ok(())
+    }
+
+    pub fn detach(&mut self, reason: &str) {
+        if let Some(mut stream) = self.attached.take() {
+            let _ = stream.write_all(format!("\r\n[detached: {reason}]\r\n").as_bytes());
+        }
+    }
+
+    // Copy the output of the process to the log file and the attached
+    // client, and the input of the client to the process. Returns false
+    // once the process closed its side of the terminal.
+    pub fn pump(&mut self) -> bool {
+        let mut buffer: [u8; 1024] = [0; 1024];
+        loop {
+            match self.master.read(&mut buffer) {
+                Ok(0) => return self._close(),
+                Ok(n) => self._output(&buffer[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                // EIO once the process closed the terminal
+                Err(_) => return self._close(),
+            }
+        }
+        let Some(stream) = self.attached.as_mut() else {
+            return true;
+        };
+        match stream.read(&mut buffer) {
+            Ok(0) => self.attached = None,
+            Ok(n) => {
+                let input: &[u8] = &buffer[..n];
+                match input.iter().position(|c| *c == DETACH_KEY) {
+                    Some(pos) => {
+                        let _ = self.master.write_all(&input[..pos]);
+                        self.detach("detach key");
+                    }
+                    None => {
+                        let _ = self.master.write_all(input);
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => self.attached = None,
+        }
+        true
+    }
+
+    // Private
+
+    fn _output(&mut self, data: &[u8]) {
+        if let Some(log_file) = self.log_file.as_mut() {
+            let _ = log_file.write_all(data);
+        }
+        self.backlog.extend_from_slice(data);
+        if self.backlog.len() > BACKLOG_SIZE {
+            self.backlog.drain(..self.backlog.len() - BACKLOG_SIZE);
+        }
+        self._send_to_client(data);
+    }
+
+    fn _send_to_client(&mut self, data: &[u8]) {
+        if let Some(stream) = self.attached.as_mut() {
+            if stream.write_all(data).is_err() {
+                self.attached = None;
+            }
+        }
+    }
+
+    fn _close(&mut self) -> bool {
+        self.detach("process exited");
+        false
+    }
+}
+
+fn _open_pty() -> io::Result<(File, File)> {
+    unsafe {
+        let master_fd: i32 = posix_openpt(O_RDWR | O_NOCTTY | O_CLOEXEC);
+        if master_fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let master: File = File::from_raw_fd(master_fd);
+        if grantpt(master_fd) == -1 || unlockpt(master_fd) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut name: [u8; 128] = [0; 128];
+        let ret: i32 = ptsname_r(master_fd, name.as_mut_ptr(), name.len());
+        if ret != 0 {
+            return Err(io::Error::from_raw_os_error(ret));
+        }
+        let slave_path: &CStr = CStr::from_bytes_until_nul(&name)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        let slave: File = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(O_NOCTTY)
+            .open(slave_path.to_string_lossy().as_ref())?;
+        let fd: i32 = master.as_raw_fd();
+        fcntl(fd, F_SETFL, fcntl(fd, F_GETFL) | O_NONBLOCK);
+        Ok((master, slave))
+    }
+}
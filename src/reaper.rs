@@ -0,0 +1,77 @@
+// Child subreaper: the orphaned descendants of the jobs are reparented to
+// taskmaster instead of init, taskmaster reaps the ones it doesn't track
+
+use crate::error::{Error, Result};
+use crate::logger::log;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+const PR_SET_CHILD_SUBREAPER: i32 = 36;
+const P_ALL: i32 = 0;
+const WNOHANG: i32 = 1;
+const WEXITED: i32 = 4;
+const WNOWAIT: i32 = 0x01000000;
+
+extern "C" {
+    fn prctl(option: i32, arg2: u64, ...) -> i32;
+    fn waitpid(pid: i32, status: *mut i32, options: i32) -> i32;
+    fn waitid(id_type: i32, id: u32, info: *mut SigInfo, options: i32) -> i32;
+}
+
+// siginfo_t of a SIGCHLD on 64-bit Linux, the fields after the pid are unused
+#[repr(C)]
+struct SigInfo {
+    signo: i32,
+    errno: i32,
+    code: i32,
+    _pad: i32,
+    pid: i32,
+    _rest: [i32; 27],
+}
+
+pub fn set_child_subreaper(enabled: bool) -> Result<()> {
+    match unsafe { prctl(PR_SET_CHILD_SUBREAPER, enabled as u64, 0u64, 0u64, 0u64) } {
+        -1 => Err(Error::Default(format!(
+            "Can't set the child subreaper flag: {}",
+            std::io::Error::last_os_error()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// Reap the zombie children of taskmaster that are not in `tracked_pids`,
+// the tracked ones are waited for by their owner, on the next pass if one
+// of them is the first zombie
+pub fn reap_orphans(tracked_pids: &[u32]) {
+    while let Some(pid) = _next_zombie_child() {
+        if tracked_pids.contains(&pid) {
+            return;
+        }
+        let mut status: i32 = 0;
+        if unsafe { waitpid(pid as i32, &mut status, WNOHANG) } != pid as i32 {
+            return;
+        }
+        log(&format!(
+            "INFO: reaped orphan pid {pid}: {}",
+            ExitStatus::from_raw(status)
+        ));
+    }
+}
+
+// Private
+
+// Pid of a child that has exited, without reaping it
+fn _next_zombie_child() -> Option<u32> {
+    let mut info: SigInfo = SigInfo {
+        signo: 0,
+        errno: 0,
+        code: 0,
+        _pad: 0,
+        pid: 0,
+        _rest: [0; 27],
+    };
+    match unsafe { waitid(P_ALL, 0, &mut info, WEXITED | WNOHANG | WNOWAIT) } {
+        0 if info.pid > 0 => Some(info.pid as u32),
+        _ => None,
+    }
+}
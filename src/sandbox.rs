@@ -0,0 +1,233 @@
+// Linux namespaces set up in the child process before exec
+
+use crate::error::{Error, Result};
+use crate::job::{tag_setup_error, SetupStep};
+use std::ffi::CString;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::ptr::null;
+
+const CLONE_NEWNS: i32 = 0x00020000;
+const CLONE_NEWPID: i32 = 0x20000000;
+const CLONE_NEWNET: i32 = 0x40000000;
+const MS_RDONLY: u64 = 1;
+const MS_NOSUID: u64 = 2;
+const MS_NODEV: u64 = 4;
+const MS_NOEXEC: u64 = 8;
+const MS_REMOUNT: u64 = 32;
+const MS_BIND: u64 = 4096;
+const MS_REC: u64 = 16384;
+const MS_PRIVATE: u64 = 1 << 18;
+const AF_INET: i32 = 2;
+const SOCK_DGRAM: i32 = 2;
+const SIOCGIFFLAGS: u64 = 0x8913;
+const SIOCSIFFLAGS: u64 = 0x8914;
+const IFF_UP: i16 = 1;
+const PR_SET_PDEATHSIG: i32 = 1;
+const SIGKILL: i32 = 9;
+const SIG_DFL: usize = 0;
+const EINTR: i32 = 4;
+const EPERM: i32 = 1;
+// signals forwarded to the process by the parent left in the old pid namespace
+const FORWARDED_SIGNALS: [i32; 6] = [1, 2, 3, 10, 12, 15];
+
+static mut NAMESPACE_CHILD_PID: i32 = 0;
+
+extern "C" {
+    fn unshare(flags: i32) -> i32;
+    fn mount(
+        source: *const i8,
+        target: *const i8,
+        fstype: *const i8,
+        flags: u64,
+        data: *const i8,
+    ) -> i32;
+    fn socket(domain: i32, kind: i32, protocol: i32) -> i32;
+    fn ioctl(fd: i32, request: u64, ...) -> i32;
+    fn close(fd: i32) -> i32;
+    fn fork() -> i32;
+    fn waitpid(pid: i32, status: *mut i32, options: i32) -> i32;
+    fn kill(pid: u32, signal: i32);
+    fn getpid() -> i32;
+    fn signal(signum: i32, handler: usize) -> u32;
+    fn prctl(option: i32, arg2: u64, ...) -> i32;
+    fn _exit(status: i32) -> !;
+}
+
+#[repr(C)]
+struct IfReq {
+    name: [u8; 16],
+    flags: i16,
+    padding: [u8; 22],
+}
+
+#[derive(Debug, Default)]
+pub struct Sandbox {
+    pub private_network: bool,
+    pub private_tmp: bool,
+    pub private_pid: bool,
+    pub read_only_paths: Vec<String>,
+}
+
+impl Sandbox {
+    pub fn is_enabled(&self) -> bool {
+        self.private_network
+            || self.private_tmp
+            || self.private_pid
+            || !self.read_only_paths.is_empty()
+    }
+
+    pub fn apply(&self, command: &mut Command) -> Result<()> {
+        let private_network: bool = self.private_network;
+        let private_tmp: bool = self.private_tmp;
+        let private_pid: bool = self.private_pid;
+        // nothing may be allocated in the child, prepare the paths now
+        let read_only_paths: Vec<CString> = self
+            .read_only_paths
+            .iter()
+            .map(|path| CString::new(path.as_str()))
+            .collect::<std::result::Result<Vec<CString>, _>>()
+            .map_err(|e| Error::StartJobFail(e.to_string()))?;
+        let mut flags: i32 = CLONE_NEWNS;
+        if private_network {
+            flags |= CLONE_NEWNET;
+        }
+        if private_pid {
+            flags |= CLONE_NEWPID;
+        }
+        unsafe {
+            let setup = move || -> io::Result<()> {
+                _check(unshare(flags))?;
+                // mounts must not propagate back to the host
+                _check(mount(
+                    null(),
+                    c"/".as_ptr(),
+                    null(),
+                    MS_REC | MS_PRIVATE,
+                    null(),
+                ))?;
+                if private_tmp {
+                    _check(mount(
+                        c"tmpfs".as_ptr(),
+                        c"/tmp".as_ptr(),
+                        c"tmpfs".as_ptr(),
+                        MS_NOSUID | MS_NODEV,
+                        c"mode=1777".as_ptr(),
+                    ))?;
+                }
+                for path in read_only_paths.iter() {
+                    _check(mount(
+                        path.as_ptr(),
+                        path.as_ptr(),
+                        null(),
+                        MS_BIND | MS_REC,
+                        null(),
+                    ))?;
+                    _check(mount(
+                        null(),
+                        path.as_ptr(),
+                        null(),
+                        MS_BIND | MS_REMOUNT | MS_RDONLY | MS_REC,
+                        null(),
+                    ))?;
+                }
+                if private_network {
+                    _loopback_up()?;
+                }
+                if private_pid {
+                    _fork_into_pid_namespace()?;
+                }
+                Ok(())
+            };
+            command.pre_exec(move || setup().map_err(|e| tag_setup_error(SetupStep::Sandbox, e)));
+        }
+        Ok(())
+    }
+}
+
+// Error of a job started with namespace options, lacking privileges is the
+// usual cause of failure
+pub fn sandbox_error(job_name: &String, e: io::Error) -> Error {
+    match e.raw_os_error() {
+        Some(EPERM) => Error::StartJobFail(format!(
+            "{job_name}: private_network, private_tmp, private_pid and read_only_paths need \
+             CAP_SYS_ADMIN, run taskmaster as root: {e}"
+        )),
+        _ => Error::StartJobFail(e.to_string()),
+    }
+}
+
+// Private
+
+fn _check(ret: i32) -> io::Result<()> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+// A new network namespace only has a loopback interface, which is down
+unsafe fn _loopback_up() -> io::Result<()> {
+    let fd: i32 = socket(AF_INET, SOCK_DGRAM, 0);
+    _check(fd)?;
+    let mut request: IfReq = IfReq {
+        name: [0; 16],
+        flags: 0,
+        padding: [0; 22],
+    };
+    request.name[..2].copy_from_slice(b"lo");
+    let ret: io::Result<()> = _check(ioctl(fd, SIOCGIFFLAGS, &mut request)).and_then(|_| {
+        request.flags |= IFF_UP;
+        _check(ioctl(fd, SIOCSIFFLAGS, &request))
+    });
+    close(fd);
+    ret
+}
+
+extern "C" fn _forward_signal(signum: i32) {
+    unsafe {
+        kill(NAMESPACE_CHILD_PID as u32, signum);
+    }
+}
+
+// Only the children of the caller of unshare(CLONE_NEWPID) are in the new
+// namespace: fork, the child becomes pid 1 and goes on to exec. The parent is
+// the process taskmaster knows, it forwards the signals and exits like the
+// child.
+unsafe fn _fork_into_pid_namespace() -> io::Result<()> {
+    let pid: i32 = fork();
+    _check(pid)?;
+    if pid == 0 {
+        _check(prctl(PR_SET_PDEATHSIG, SIGKILL as u64))?;
+        // /proc of the new namespace, for ps and friends
+        return _check(mount(
+            c"proc".as_ptr(),
+            c"/proc".as_ptr(),
+            c"proc".as_ptr(),
+            MS_NOSUID | MS_NODEV | MS_NOEXEC,
+            null(),
+        ));
+    }
+    NAMESPACE_CHILD_PID = pid;
+    // the pipes of taskmaster must only be held by the child
+    for fd in 0..1024 {
+        close(fd);
+    }
+    for signum in FORWARDED_SIGNALS {
+        signal(signum, _forward_signal as *const () as usize);
+    }
+    let mut status: i32 = 0;
+    while waitpid(pid, &mut status, 0) == -1 {
+        if io::Error::last_os_error().raw_os_error() != Some(EINTR) {
+            _exit(1);
+        }
+    }
+    let signum: i32 = status & 0x7f;
+    if signum == 0 {
+        _exit((status >> 8) & 0xff);
+    }
+    signal(signum, SIG_DFL);
+    kill(getpid() as u32, signum);
+    _exit(128 + signum);
+}
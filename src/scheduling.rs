@@ -0,0 +1,134 @@
+// Priority, CPU affinity, I/O priority and OOM score applied in the child
+// process before exec
+
+use crate::error::{Error, Result};
+use crate::job::{tag_setup_error, IoPriorityClass, SetupStep};
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+const PRIO_PROCESS: i32 = 0;
+const IOPRIO_WHO_PROCESS: i64 = 1;
+const IOPRIO_CLASS_SHIFT: i64 = 13;
+const O_WRONLY: i32 = 1;
+const O_CLOEXEC: i32 = 0o2000000;
+const EPERM: i32 = 1;
+const EACCES: i32 = 13;
+const EINVAL: i32 = 22;
+// cpu_set_t of the glibc
+pub const MAX_CPUS: usize = 1024;
+#[cfg(target_arch = "x86_64")]
+const SYS_IOPRIO_SET: Option<i64> = Some(251);
+#[cfg(target_arch = "aarch64")]
+const SYS_IOPRIO_SET: Option<i64> = Some(30);
+// the number of the syscall differs on the other architectures
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const SYS_IOPRIO_SET: Option<i64> = None;
+
+extern "C" {
+    fn setpriority(which: i32, who: u32, prio: i32) -> i32;
+    fn sched_setaffinity(pid: i32, cpusetsize: usize, mask: *const u64) -> i32;
+    fn syscall(number: i64, ...) -> i64;
+    fn open(path: *const i8, flags: i32, ...) -> i32;
+    fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+    fn close(fd: i32) -> i32;
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduling {
+    pub nice: Option<i32>,
+    pub cpu_affinity: Option<Vec<usize>>,
+    pub io_priority: Option<(IoPriorityClass, u32)>,
+    pub oom_score_adj: Option<i32>,
+}
+
+impl Scheduling {
+    pub fn is_enabled(&self) -> bool {
+        self.nice.is_some()
+            || self.cpu_affinity.is_some()
+            || self.io_priority.is_some()
+            || self.oom_score_adj.is_some()
+    }
+
+    pub fn apply(&self, command: &mut Command) -> Result<()> {
+        let io_priority_syscall: i64 = match (&self.io_priority, SYS_IOPRIO_SET) {
+            (Some(_), None) => {
+                return Err(Error::StartJobFail(
+                    "ioprio_class and ioprio_level are not supported on this architecture".into(),
+                ))
+            }
+            (_, syscall_number) => syscall_number.unwrap_or_default(),
+        };
+        let nice: Option<i32> = self.nice;
+        let cpu_mask: Option<[u64; MAX_CPUS / 64]> = self.cpu_affinity.as_ref().map(|cpus| {
+            let mut mask: [u64; MAX_CPUS / 64] = [0; MAX_CPUS / 64];
+            for cpu in cpus {
+                mask[cpu / 64] |= 1 << (cpu % 64);
+            }
+            mask
+        });
+        let io_priority: Option<i64> = self
+            .io_priority
+            .as_ref()
+            .map(|(class, level)| ((class.clone() as i64) << IOPRIO_CLASS_SHIFT) | *level as i64);
+        // nothing may be allocated in the child, format the value now
+        let oom_score_adj: Option<Vec<u8>> = self
+            .oom_score_adj
+            .map(|score| score.to_string().into_bytes());
+        unsafe {
+            let setup = move || -> io::Result<()> {
+                if let Some(nice) = nice {
+                    _check(setpriority(PRIO_PROCESS, 0, nice) as i64)?;
+                }
+                if let Some(mask) = cpu_mask {
+                    _check(sched_setaffinity(0, MAX_CPUS / 8, mask.as_ptr()) as i64)?;
+                }
+                if let Some(io_priority) = io_priority {
+                    _check(syscall(
+                        io_priority_syscall,
+                        IOPRIO_WHO_PROCESS,
+                        0i64,
+                        io_priority,
+                    ))?;
+                }
+                if let Some(ref score) = oom_score_adj {
+                    _write_oom_score_adj(score)?;
+                }
+                Ok(())
+            };
+            command
+                .pre_exec(move || setup().map_err(|e| tag_setup_error(SetupStep::Scheduling, e)));
+        }
+        Ok(())
+    }
+}
+
+pub fn scheduling_error(job_name: &String, e: io::Error) -> Error {
+    match e.raw_os_error() {
+        Some(EPERM) | Some(EACCES) => Error::StartJobFail(format!(
+            "{job_name}: a negative nice or oom_score_adj and the realtime ioprio_class need \
+             root privileges: {e}"
+        )),
+        Some(EINVAL) => Error::StartJobFail(format!(
+            "{job_name}: cpu_affinity must list CPUs of this machine: {e}"
+        )),
+        _ => Error::StartJobFail(e.to_string()),
+    }
+}
+
+// Private
+
+fn _check(ret: i64) -> io::Result<()> {
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+unsafe fn _write_oom_score_adj(score: &[u8]) -> io::Result<()> {
+    let fd: i32 = open(c"/proc/self/oom_score_adj".as_ptr(), O_WRONLY | O_CLOEXEC);
+    _check(fd as i64)?;
+    let ret: isize = write(fd, score.as_ptr(), score.len());
+    close(fd);
+    _check(ret as i64)
+}
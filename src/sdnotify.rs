@@ -0,0 +1,65 @@
+// sd_notify protocol: the processes send datagrams such as
+// `READY=1\nSTATUS=Listening on :8000` to the socket named by NOTIFY_SOCKET
+// https://www.freedesktop.org/software/systemd/man/sd_notify.html
+
+use crate::error::{Error, Result};
+use std::fs;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+
+const NOTIFY_DIR: &str = "taskmaster.notify";
+// sun_path of sockaddr_un, without the terminating null byte
+const MAX_SOCKET_PATH: usize = 107;
+
+#[derive(Debug)]
+pub struct NotifySocket {
+    socket: UnixDatagram,
+    pub path: PathBuf,
+}
+
+impl NotifySocket {
+    // The path only depends on the job and the process index, so a process
+    // adopted by a new taskmaster instance still reaches the socket
+    pub fn bind(job_name: &str, process_index: usize) -> Result<NotifySocket> {
+        let to_error = |e: std::io::Error| Error::StartJobFail(format!("{NOTIFY_DIR}: {e}"));
+        let dir: PathBuf = std::env::current_dir().map_err(to_error)?.join(NOTIFY_DIR);
+        fs::create_dir_all(&dir).map_err(to_error)?;
+        let path: PathBuf = dir.join(format!("{job_name}_{process_index}.sock"));
+        if path.as_os_str().len() > MAX_SOCKET_PATH {
+            return Err(Error::StartJobFail(format!(
+                "{} is too long for a socket path",
+                path.display()
+            )));
+        }
+        // left by a previous instance
+        let _ = fs::remove_file(&path);
+        let socket: UnixDatagram = UnixDatagram::bind(&path).map_err(to_error)?;
+        socket.set_nonblocking(true).map_err(to_error)?;
+        Ok(NotifySocket { socket, path })
+    }
+
+    // Variables of the messages received since the last call, in order
+    pub fn receive(&self) -> Vec<(String, String)> {
+        let mut buffer: [u8; 4096] = [0; 4096];
+        let mut variables: Vec<(String, String)> = Vec::new();
+        while let Ok(n) = self.socket.recv(&mut buffer) {
+            variables.extend(parse_notify_message(&String::from_utf8_lossy(&buffer[..n])));
+        }
+        variables
+    }
+}
+
+impl Drop for NotifySocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// One `KEY=VALUE` assignment per line
+pub fn parse_notify_message(message: &str) -> Vec<(String, String)> {
+    message
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
@@ -0,0 +1,85 @@
+use crate::error::{Error, Result};
+use crate::job::ProcessStates;
+use std::fs;
+
+// One line of the state file: `job_name index pid start_time state [retired]`
+#[derive(Debug, PartialEq, Clone)]
+pub struct StateEntry {
+    pub job_name: String,
+    pub index: usize,
+    pub pid: u32,
+    pub start_time: u64,
+    pub state: ProcessStates,
+    // process of a job removed or replaced by a reload, still being stopped
+    pub retired: bool,
+}
+
+impl StateEntry {
+    pub fn to_line(&self) -> String {
+        let line: String = format!(
+            "{} {} {} {} {:?}",
+            self.job_name, self.index, self.pid, self.start_time, self.state
+        );
+        match self.retired {
+            true => format!("{line} retired"),
+            false => line,
+        }
+    }
+
+    pub fn from_line(line: &str) -> Option<StateEntry> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let retired: bool = match fields.get(5) {
+            None if fields.len() == 5 => false,
+            Some(&"retired") if fields.len() == 6 => true,
+            _ => return None,
+        };
+        let state: ProcessStates = match fields[4] {
+            "Starting" => ProcessStates::Starting,
+            "Running" => ProcessStates::Running,
+            "Stopping" => ProcessStates::Stopping,
+            _ => return None,
+        };
+        Some(StateEntry {
+            job_name: fields[0].to_string(),
+            index: fields[1].parse().ok()?,
+            pid: fields[2].parse().ok()?,
+            start_time: fields[3].parse().ok()?,
+            state,
+            retired,
+        })
+    }
+}
+
+pub fn serialize_state(entries: &[StateEntry]) -> String {
+    entries.iter().map(|entry| entry.to_line() + "\n").collect()
+}
+
+pub fn save_state(path: &str, content: &String) -> Result<()> {
+    // write then rename so a crash never leaves a truncated state file
+    let tmp_path: String = format!("{path}.tmp");
+    fs::write(&tmp_path, content).map_err(|e| Error::IO(e.to_string()))?;
+    fs::rename(&tmp_path, path).map_err(|e| Error::IO(e.to_string()))?;
+    Ok(())
+}
+
+pub fn load_state(path: &str) -> Vec<StateEntry> {
+    let Ok(content) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    content.lines().filter_map(StateEntry::from_line).collect()
+}
+
+// Field 22 of /proc/<pid>/stat: start time of the process in clock ticks
+// since boot. Together with the pid it identifies a process, since pids
+// are recycled.
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let stat: String = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // the command name is between parentheses and may contain spaces
+    let after_comm: &str = &stat[stat.rfind(')')? + 1..];
+    let fields: Vec<&str> = after_comm.split_whitespace().collect();
+    // fields[0] is field 3 (state), zombies are already dead
+    if fields.first()? == &"Z" {
+        return None;
+    }
+    fields.get(19)?.parse::<u64>().ok()
+}
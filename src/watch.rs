@@ -0,0 +1,139 @@
+use crate::error::{Error, Result};
+use crate::glob::{glob_match, is_glob};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::os::fd::FromRawFd;
+use std::path::Path;
+
+const IN_NONBLOCK: i32 = 0o4000;
+const IN_CLOEXEC: i32 = 0o2000000;
+const IN_MODIFY: u32 = 0x2;
+const IN_ATTRIB: u32 = 0x4;
+const IN_CLOSE_WRITE: u32 = 0x8;
+const IN_MOVED_FROM: u32 = 0x40;
+const IN_MOVED_TO: u32 = 0x80;
+const IN_CREATE: u32 = 0x100;
+const IN_DELETE: u32 = 0x200;
+// wd, mask, cookie and len fields of struct inotify_event
+const EVENT_HEADER_SIZE: usize = 16;
+
+extern "C" {
+    fn inotify_init1(flags: i32) -> i32;
+    fn inotify_add_watch(fd: i32, pathname: *const i8, mask: u32) -> i32;
+}
+
+// Watch paths with inotify. A path is either a file, a directory (any
+// change inside it matches) or a directory followed by a pattern such as
+// `src/*.py`.
+#[derive(Debug)]
+pub struct Watcher {
+    inotify: File,
+    // watch descriptor -> patterns the changed file name must match, None
+    // matches any file of the directory
+    patterns: HashMap<i32, Vec<Option<String>>>,
+}
+
+impl Watcher {
+    pub fn new(paths: &Vec<String>) -> Result<Watcher> {
+        let fd: i32 = unsafe { inotify_init1(IN_NONBLOCK | IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(Error::IO(std::io::Error::last_os_error().to_string()));
+        }
+        let mut watcher = Watcher {
+            inotify: unsafe { File::from_raw_fd(fd) },
+            patterns: HashMap::new(),
+        };
+        for path in paths {
+            let path: &Path = Path::new(path);
+            let (dir, pattern) = if path.is_dir() {
+                (path, None)
+            } else {
+                let dir: &Path = match path.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+                let name: String = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                (dir, Some(name))
+            };
+            watcher._add_watch(fd, dir, pattern)?;
+        }
+        Ok(watcher)
+    }
+
+    // Consume the pending events, returns true if one of them matches
+    pub fn poll(&mut self) -> bool {
+        let mut changed: bool = false;
+        let mut buffer: [u8; 4096] = [0; 4096];
+        loop {
+            let bytes_read: usize = match self.inotify.read(&mut buffer) {
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => break,
+            };
+            let mut offset: usize = 0;
+            while offset + EVENT_HEADER_SIZE <= bytes_read {
+                let field = |at: usize| {
+                    let bytes: [u8; 4] = buffer[offset + at..offset + at + 4].try_into().unwrap();
+                    u32::from_ne_bytes(bytes)
+                };
+                let wd: i32 = field(0) as i32;
+                let len: usize = field(12) as usize;
+                let name_bytes: &[u8] =
+                    &buffer[offset + EVENT_HEADER_SIZE..offset + EVENT_HEADER_SIZE + len];
+                let name: String = String::from_utf8_lossy(name_bytes)
+                    .trim_end_matches('\0')
+                    .to_string();
+                changed |= self.patterns.get(&wd).is_some_and(|patterns| {
+                    patterns.iter().any(|pattern| match pattern {
+                        Some(pattern) => glob_match(pattern, &name),
+                        None => true,
+                    })
+                });
+                offset += EVENT_HEADER_SIZE + len;
+            }
+        }
+        changed
+    }
+
+    // Private
+
+    fn _add_watch(&mut self, fd: i32, dir: &Path, pattern: Option<String>) -> Result<()> {
+        let to_error = |msg: String| Error::FieldBadFormat {
+            field_name: String::from("watch"),
+            msg,
+        };
+        if let Some(ref pattern) = pattern {
+            if !is_glob(pattern) && !dir.join(pattern).exists() {
+                return Err(to_error(format!(
+                    "{} doesn't exist",
+                    dir.join(pattern).display()
+                )));
+            }
+        }
+        let c_dir =
+            CString::new(dir.to_string_lossy().as_bytes()).map_err(|e| to_error(e.to_string()))?;
+        let mask: u32 = IN_MODIFY
+            | IN_ATTRIB
+            | IN_CLOSE_WRITE
+            | IN_MOVED_FROM
+            | IN_MOVED_TO
+            | IN_CREATE
+            | IN_DELETE;
+        let wd: i32 = unsafe { inotify_add_watch(fd, c_dir.as_ptr(), mask) };
+        if wd == -1 {
+            return Err(to_error(format!(
+                "{}: {}",
+                dir.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+        // the same directory can be watched with several patterns
+        self.patterns.entry(wd).or_default().push(pattern);
+        Ok(())
+    }
+}